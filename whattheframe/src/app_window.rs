@@ -1,14 +1,17 @@
 use crate::views::Views;
 use gtk4::prelude::{
-    ApplicationExt, BoxExt, ButtonExt, FileChooserExt, GtkWindowExt, NativeDialogExt, WidgetExt,
+    ApplicationExt, BoxExt, ButtonExt, EditableExt, EntryExt, FileChooserExt, GtkWindowExt,
+    NativeDialogExt, PopoverExt, WidgetExt,
 };
 use gtk4::{
-    Application, Box as GtkBox, Button, CallbackAction, FileChooserAction, FileChooserNative,
-    FileFilter, InfoBar, Label, MessageType, ResponseType, Shortcut, ShortcutController,
-    ShortcutTrigger,
+    Application, Box as GtkBox, Button, CallbackAction, Entry, FileChooserAction,
+    FileChooserNative, FileFilter, InfoBar, Label, MenuButton, MessageType, Popover, ResponseType,
+    Shortcut, ShortcutController, ShortcutTrigger,
 };
 use libadwaita::prelude::ApplicationWindowExt;
 use libadwaita::{ApplicationWindow, HeaderBar, ViewSwitcher};
+use std::rc::Rc;
+use wtf::ServeAddress;
 
 pub struct AppWindow {}
 
@@ -21,8 +24,16 @@ impl AppWindow {
         load_profile_error_bar.set_show_close_button(true);
         load_profile_error_bar.connect_response(|bar, _| bar.hide());
         load_profile_error_bar.hide();
+        let show_error = {
+            let load_profile_error_label = load_profile_error_label.clone();
+            let load_profile_error_bar = load_profile_error_bar.clone();
+            move |message: String| {
+                load_profile_error_label.set_label(&message);
+                load_profile_error_bar.show();
+            }
+        };
 
-        let views = Views::new();
+        let views = Rc::new(Views::new());
 
         let content_area = GtkBox::new(gtk4::Orientation::Vertical, 0);
         content_area.append(&load_profile_error_bar);
@@ -30,10 +41,26 @@ impl AppWindow {
 
         let open_profile_button = Button::with_label("Open Profile");
 
+        // Connect to a program serving frames with wtf::ProfilerConfig::serve
+        let connect_entry = Entry::new();
+        connect_entry.set_placeholder_text(Some("127.0.0.1:7777 or unix:/path/to/socket"));
+        connect_entry.set_width_chars(32);
+        let connect_button = Button::with_label("Connect");
+        connect_button.add_css_class("suggested-action");
+        let connect_box = GtkBox::new(gtk4::Orientation::Horizontal, 6);
+        connect_box.append(&connect_entry);
+        connect_box.append(&connect_button);
+        let connect_popover = Popover::new();
+        connect_popover.set_child(Some(&connect_box));
+        let connect_menu_button = MenuButton::new();
+        connect_menu_button.set_label("Connect");
+        connect_menu_button.set_popover(Some(&connect_popover));
+
         let view_switcher = ViewSwitcher::new();
 
         let header_bar = HeaderBar::new();
         header_bar.pack_start(&open_profile_button);
+        header_bar.pack_start(&connect_menu_button);
         header_bar.set_title_widget(Some(&view_switcher));
 
         let window_content = GtkBox::new(gtk4::Orientation::Vertical, 0);
@@ -81,16 +108,40 @@ impl AppWindow {
         );
         shortcut_controller.add_shortcut(&open_profile_shorcut);
 
-        file_chooser.connect_response(move |file_chooser, response| {
-            if response == ResponseType::Accept {
-                if let Some(profile) = file_chooser.file() {
-                    match views.load_profile(profile) {
-                        Ok(views) => view_switcher.set_stack(Some(views)),
-                        Err(_) => load_profile_error_bar.show(),
+        file_chooser.connect_response({
+            let views = views.clone();
+            let view_switcher = view_switcher.clone();
+            let show_error = show_error.clone();
+            move |file_chooser, response| {
+                if response == ResponseType::Accept {
+                    if let Some(profile) = file_chooser.file() {
+                        match views.load_profile(profile) {
+                            Ok(views) => view_switcher.set_stack(Some(views)),
+                            Err(err) => show_error(format!("Failed to Load Profile: {}", err)),
+                        }
+                    }
+                }
+            }
+        });
+
+        let connect = Rc::new({
+            let connect_entry = connect_entry.clone();
+            move || {
+                connect_popover.popdown();
+                match connect_entry.text().parse::<ServeAddress>() {
+                    Ok(address) => {
+                        let views = views.connect(address, show_error.clone());
+                        view_switcher.set_stack(Some(views));
                     }
+                    Err(err) => show_error(format!("Invalid Address: {}", err)),
                 }
             }
         });
+        connect_button.connect_clicked({
+            let connect = connect.clone();
+            move |_| connect()
+        });
+        connect_entry.connect_activate(move |_| connect());
 
         application.connect_activate(move |_| {
             window.show();
@@ -1,24 +1,35 @@
 use crate::task_object::TaskObject;
 use gtk4::cairo::Context;
 use gtk4::glib::{self, Object};
-use gtk4::prelude::DrawingAreaExt;
+use gtk4::prelude::{Cast, DrawingAreaExt, WidgetExt};
 use gtk4::subclass::prelude::{
     DrawingAreaImpl, ObjectImpl, ObjectImplExt, ObjectSubclass, ObjectSubclassExt, WidgetImpl,
 };
 use gtk4::{DrawingArea, Widget};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::f64::consts::PI;
 use std::rc::Rc;
 use std::time::Duration;
 use wtf::TaskData;
 
 pub const FRAME_WIDTH: i32 = 12;
 pub const FRAME_HEIGHT: i32 = 140;
+pub const COUNTER_HEIGHT: i32 = 40;
+
+/// The counter plotted below the frames, shared by every frame in the timeline
+#[derive(Default)]
+pub struct CounterPlot {
+    pub name: RefCell<Option<Box<str>>>,
+    /// The largest value of the counter, which is plotted at the top
+    pub max: Cell<f64>,
+}
 
 mod inner {
     use super::*;
 
     pub struct Frame {
         pub data: Rc<RefCell<TaskObject>>,
+        pub counter_plot: RefCell<Rc<CounterPlot>>,
     }
 
     #[glib::object_subclass]
@@ -29,11 +40,8 @@ mod inner {
 
         fn new() -> Self {
             Self {
-                data: Rc::new(RefCell::new(TaskObject::new(TaskData {
-                    name: Box::from(""),
-                    duration: Duration::default(),
-                    subtasks: Box::new([]),
-                }))),
+                data: Rc::new(RefCell::new(TaskObject::new(TaskData::default()))),
+                counter_plot: RefCell::new(Rc::new(CounterPlot::default())),
             }
         }
     }
@@ -47,8 +55,9 @@ mod inner {
 
             obj.set_draw_func({
                 let data = self.data.clone();
-                move |_: &DrawingArea, canvas: &Context, _: i32, _: i32| {
+                move |area: &DrawingArea, canvas: &Context, _: i32, _: i32| {
                     let duration = data.borrow().get().duration;
+                    let dropped_tasks = data.borrow().get().dropped_tasks;
                     let duration_ms = duration.as_secs_f64() * 1000.0;
                     let height = (duration_ms / 24.0).clamp(0.05, 1.0) * (FRAME_HEIGHT as f64);
 
@@ -64,6 +73,65 @@ mod inner {
                         canvas.set_source_rgb(98.0 / 255.0, 160.0 / 255.0, 234.0 / 255.0);
                     }
                     canvas.fill().unwrap();
+
+                    // Mark frames that are missing tasks
+                    if dropped_tasks > 0 {
+                        canvas.rectangle(1.0, 0.0, FRAME_WIDTH as f64, 4.0);
+                        canvas.set_source_rgb(255.0 / 255.0, 163.0 / 255.0, 72.0 / 255.0);
+                        canvas.fill().unwrap();
+                    }
+
+                    // Mark the moments recorded during the frame, at the point in the frame they happened
+                    let frame_start = data.borrow().get().start;
+                    for mark in data.borrow().get().marks.iter() {
+                        let fraction = match frame_start {
+                            Some(frame_start) if duration > Duration::default() => {
+                                (mark.time.saturating_sub(frame_start).as_secs_f64()
+                                    / duration.as_secs_f64())
+                                .clamp(0.0, 1.0)
+                            }
+                            _ => 0.5,
+                        };
+                        canvas.arc(
+                            1.0 + fraction * FRAME_WIDTH as f64,
+                            10.0,
+                            2.5,
+                            0.0,
+                            2.0 * PI,
+                        );
+                        canvas.set_source_rgb(246.0 / 255.0, 211.0 / 255.0, 45.0 / 255.0);
+                        canvas.fill().unwrap();
+                    }
+
+                    // Plot the selected counter below the frame, as a step of the line across the timeline
+                    let frame = Frame::from_instance(area.downcast_ref::<super::Frame>().unwrap());
+                    let counter_plot = frame.counter_plot.borrow();
+                    let value = match &*counter_plot.name.borrow() {
+                        Some(name) => data
+                            .borrow()
+                            .get()
+                            .counters
+                            .iter()
+                            .find(|(counter, _)| counter == name)
+                            .map(|(_, value)| *value),
+                        None => None,
+                    };
+                    if let Some(value) = value {
+                        let max = counter_plot.max.get();
+                        let fraction = if max > 0.0 {
+                            (value / max).clamp(0.0, 1.0)
+                        } else {
+                            0.0
+                        };
+                        let y = (FRAME_HEIGHT + COUNTER_HEIGHT) as f64
+                            - 2.0
+                            - fraction * (COUNTER_HEIGHT - 4) as f64;
+                        canvas.move_to(1.0, y);
+                        canvas.line_to(1.0 + FRAME_WIDTH as f64, y);
+                        canvas.set_source_rgb(145.0 / 255.0, 65.0 / 255.0, 172.0 / 255.0);
+                        canvas.set_line_width(2.0);
+                        canvas.stroke().unwrap();
+                    }
                 }
             });
         }
@@ -84,6 +152,45 @@ impl Frame {
     }
 
     pub fn set_data(&self, data: TaskObject) {
+        let mut tooltip = Vec::new();
+        let dropped_tasks = data.get().dropped_tasks;
+        if dropped_tasks > 0 {
+            tooltip.push(format!(
+                "Incomplete frame, {} tasks were dropped",
+                dropped_tasks
+            ));
+        }
+        for (name, value) in data.get().counters.iter() {
+            tooltip.push(format!("{}: {}", name, value));
+        }
+        for mark in data.get().marks.iter() {
+            match &mark.payload {
+                Some(payload) => tooltip.push(format!("Mark {} ({})", mark.name, payload)),
+                None => tooltip.push(format!("Mark {}", mark.name)),
+            }
+        }
+        if tooltip.is_empty() {
+            self.set_tooltip_text(None);
+        } else {
+            self.set_tooltip_text(Some(&tooltip.join("\n")));
+        }
         *inner::Frame::from_instance(self).data.borrow_mut() = data;
+        self.update_counter_plot();
+    }
+
+    pub fn set_counter_plot(&self, counter_plot: Rc<CounterPlot>) {
+        *inner::Frame::from_instance(self).counter_plot.borrow_mut() = counter_plot;
+        self.update_counter_plot();
+    }
+
+    /// Redraw the frame after the plotted counter changed
+    pub fn update_counter_plot(&self) {
+        let counter_plot = inner::Frame::from_instance(self).counter_plot.borrow();
+        if counter_plot.name.borrow().is_some() {
+            self.set_content_height(FRAME_HEIGHT + COUNTER_HEIGHT);
+        } else {
+            self.set_content_height(FRAME_HEIGHT);
+        }
+        self.queue_draw();
     }
 }
@@ -1,26 +1,45 @@
-use crate::frame_view::{Frame, FrameThreshold, FRAME_HEIGHT};
+use crate::frame_view::{CounterPlot, Frame, FrameThreshold, COUNTER_HEIGHT, FRAME_HEIGHT};
 use crate::task_object::TaskObject;
 use gtk4::gio::ListStore;
 use gtk4::glib::types::Type;
-use gtk4::prelude::{Cast, CheckButtonExt, OrientableExt, SelectionModelExt, WidgetExt};
+use gtk4::prelude::{
+    Cast, CheckButtonExt, ComboBoxExt, OrientableExt, SelectionModelExt, WidgetExt,
+};
 use gtk4::{
-    Align, CheckButton, CustomFilter, FilterListModel, ListView, Orientation, Overlay,
-    ScrolledWindow, SignalListItemFactory, SingleSelection, NONE_FILTER, NONE_SELECTION_MODEL,
-    NONE_WIDGET,
+    Align, CheckButton, ComboBoxText, CustomFilter, FilterListModel, ListView, Orientation,
+    Overlay, ScrolledWindow, SignalListItemFactory, SingleSelection, NONE_FILTER,
+    NONE_SELECTION_MODEL, NONE_WIDGET,
 };
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 use std::time::Duration;
 
 pub struct FrameTimeline {
     widget: Overlay,
+    scrolled_window: ScrolledWindow,
     list_view: ListView,
     threshold_toggle: CheckButton,
+    counter_select: ComboBoxText,
+    counter_plot: Rc<CounterPlot>,
+    /// Each counter recorded in the frames, and its largest value, in the order they're listed in `counter_select`
+    counters: Rc<RefCell<Vec<(Box<str>, f64)>>>,
+    /// The frames shown, before filtering
+    frames: RefCell<ListStore>,
+    above_threshold_count: Cell<usize>,
 }
 
 impl FrameTimeline {
     pub fn new() -> Self {
+        let counter_plot = Rc::new(CounterPlot::default());
+
         let factory = SignalListItemFactory::new();
-        factory.connect_setup(|_, list_item| {
-            list_item.set_child(Some(&Frame::new()));
+        factory.connect_setup({
+            let counter_plot = counter_plot.clone();
+            move |_, list_item| {
+                let frame = Frame::new();
+                frame.set_counter_plot(counter_plot.clone());
+                list_item.set_child(Some(&frame));
+            }
         });
         factory.connect_bind(|_, list_item| {
             let frame = list_item.child().unwrap().downcast::<Frame>().unwrap();
@@ -52,15 +71,44 @@ impl FrameTimeline {
         threshold_toggle.set_halign(Align::Start);
         threshold_toggle.set_valign(Align::Start);
 
+        let counter_select = ComboBoxText::new();
+        counter_select.set_halign(Align::End);
+        counter_select.set_valign(Align::Start);
+        counter_select.set_tooltip_text(Some("Counter to plot below the frames"));
+
+        let counters = Rc::new(RefCell::new(Vec::new()));
+        counter_select.connect_changed({
+            let scrolled_window = scrolled_window.clone();
+            let list_view = list_view.clone();
+            let counter_plot = counter_plot.clone();
+            let counters = counters.clone();
+            move |counter_select| {
+                plot_counter(
+                    counter_select,
+                    &counters.borrow(),
+                    &counter_plot,
+                    &scrolled_window,
+                    &list_view,
+                );
+            }
+        });
+
         let widget = Overlay::new();
         widget.set_child(Some(&scrolled_window));
         widget.add_overlay(frame_threshold.widget());
         widget.add_overlay(&threshold_toggle);
+        widget.add_overlay(&counter_select);
 
         Self {
             widget,
+            scrolled_window,
             list_view,
             threshold_toggle,
+            counter_select,
+            counter_plot,
+            counters,
+            frames: RefCell::new(ListStore::new(Type::OBJECT)),
+            above_threshold_count: Cell::new(0),
         }
     }
 
@@ -76,12 +124,11 @@ impl FrameTimeline {
         for frame in frames {
             model.append(frame);
         }
+        *self.frames.borrow_mut() = model.clone();
         let model = FilterListModel::new(Some(&model), NONE_FILTER);
 
-        self.threshold_toggle.set_label(Some(&format!(
-            "Filter Threshhold ({})",
-            above_threshold_count
-        )));
+        self.above_threshold_count.set(above_threshold_count);
+        self.update_threshold_label();
         self.threshold_toggle.connect_toggled({
             let model = model.clone();
             move |threshold_toggle| {
@@ -106,9 +153,122 @@ impl FrameTimeline {
         });
 
         self.list_view.set_model(Some(&model));
+
+        self.load_counters(frames);
+    }
+
+    /// Add a frame to the end of the timeline, such as one streamed from a running program
+    pub fn append_frame(&self, frame: &TaskObject) {
+        self.frames.borrow().append(frame);
+        if frame.get().duration > Duration::from_nanos(16666670) {
+            self.above_threshold_count
+                .set(self.above_threshold_count.get() + 1);
+            self.update_threshold_label();
+        }
+
+        // Add the frame's counters to the list, and raise their largest values
+        let mut plotted_max_changed = false;
+        let old_len = {
+            let mut counters = self.counters.borrow_mut();
+            let old_len = counters.len();
+            let plotted = self.counter_plot.name.borrow();
+            for (name, value) in frame.get().counters.iter() {
+                match counters.iter_mut().find(|(counter, _)| counter == name) {
+                    Some((_, max)) if *value > *max => {
+                        *max = *value;
+                        plotted_max_changed |= plotted.as_ref() == Some(name);
+                    }
+                    Some(_) => {}
+                    None => counters.push((name.clone(), *value)),
+                }
+            }
+            old_len
+        };
+
+        for (name, _) in &self.counters.borrow()[old_len..] {
+            self.counter_select.append_text(name);
+        }
+        if old_len == 0 && !self.counters.borrow().is_empty() {
+            // Plot the first counter as soon as there is one, like when loading a profile
+            self.counter_select.set_visible(true);
+            self.counter_select.set_active(Some(1));
+        } else if plotted_max_changed {
+            plot_counter(
+                &self.counter_select,
+                &self.counters.borrow(),
+                &self.counter_plot,
+                &self.scrolled_window,
+                &self.list_view,
+            );
+        }
+    }
+
+    fn update_threshold_label(&self) {
+        self.threshold_toggle.set_label(Some(&format!(
+            "Filter Threshhold ({})",
+            self.above_threshold_count.get()
+        )));
+    }
+
+    /// List the counters recorded in the profile, and plot the first one
+    fn load_counters(&self, frames: &[TaskObject]) {
+        {
+            let mut counters = self.counters.borrow_mut();
+            counters.clear();
+            for frame in frames {
+                for (name, value) in frame.get().counters.iter() {
+                    match counters.iter_mut().find(|(counter, _)| counter == name) {
+                        Some((_, max)) => *max = max.max(*value),
+                        None => counters.push((name.clone(), *value)),
+                    }
+                }
+            }
+        }
+
+        let counters = self.counters.borrow();
+        self.counter_select.remove_all();
+        self.counter_select.append_text("No Counter");
+        for (name, _) in counters.iter() {
+            self.counter_select.append_text(name);
+        }
+        self.counter_select.set_visible(!counters.is_empty());
+        self.counter_select
+            .set_active(Some(if counters.is_empty() { 0 } else { 1 }));
     }
 
     pub fn widget(&self) -> &Overlay {
         &self.widget
     }
 }
+
+/// Plot the counter selected in `counter_select` below each frame, or hide the plot if none is selected
+fn plot_counter(
+    counter_select: &ComboBoxText,
+    counters: &[(Box<str>, f64)],
+    counter_plot: &CounterPlot,
+    scrolled_window: &ScrolledWindow,
+    list_view: &ListView,
+) {
+    // The first entry is "No Counter"
+    let counter = counter_select
+        .active()
+        .and_then(|i| i.checked_sub(1))
+        .and_then(|i| counters.get(i as usize));
+    *counter_plot.name.borrow_mut() = counter.map(|(name, _)| name.clone());
+    counter_plot.max.set(counter.map_or(0.0, |(_, max)| *max));
+
+    let counter_height = if counter.is_some() { COUNTER_HEIGHT } else { 0 };
+    scrolled_window.set_min_content_height(FRAME_HEIGHT + counter_height + 10);
+
+    // Only the frames currently shown have widgets
+    let mut list_item = list_view.first_child();
+    while let Some(item) = list_item {
+        let frame = item
+            .first_child()
+            .and_then(|child| child.downcast::<Frame>().ok());
+        if let Some(frame) = frame {
+            frame.update_counter_plot();
+        }
+        list_item = item.next_sibling();
+    }
+}
@@ -41,6 +41,10 @@ impl FrameView {
         );
     }
 
+    pub fn append_frame(&self, frame: &TaskObject) {
+        self.frame_timeline.append_frame(frame);
+    }
+
     pub fn widget(&self) -> &GtkBox {
         &self.widget
     }
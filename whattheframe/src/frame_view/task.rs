@@ -1,3 +1,4 @@
+use crate::mark_object::MarkObject;
 use crate::task_object::TaskObject;
 use gtk4::glib::{self, Object};
 use gtk4::prelude::{BoxExt, OrientableExt, WidgetExt};
@@ -6,6 +7,7 @@ use gtk4::subclass::prelude::{
     WidgetImpl,
 };
 use gtk4::{Box as GtkBox, Label, Orientable, Orientation, Widget};
+use std::time::Duration;
 
 mod inner {
     use super::*;
@@ -71,11 +73,57 @@ impl Task {
                 let task_duration_ms = task.duration.as_secs_f64() * 1000.0;
                 let task_duation_label = format!("{:.2}ms", task_duration_ms);
                 this.duration_label.set_label(&task_duation_label);
+
+                // Show the task's args when hovering over it
+                if task.args.is_empty() {
+                    self.set_tooltip_text(None);
+                } else {
+                    let tooltip = task
+                        .args
+                        .iter()
+                        .map(|(key, value)| format!("{}: {}", key, value))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    self.set_tooltip_text(Some(&tooltip));
+                }
             }
             None => {
                 this.name_label.set_label("");
                 this.duration_label.set_label("");
+                self.set_tooltip_text(None);
             }
         }
     }
+
+    /// Show a mark in place of a task, with its time relative to the start of the frame it's in
+    pub fn set_mark(&self, mark: &MarkObject, frame_start: Option<Duration>) {
+        let this = inner::Task::from_instance(self);
+        let mark = mark.get();
+        match &mark.payload {
+            Some(payload) => this
+                .name_label
+                .set_label(&format!("\u{25C6} {} ({})", mark.name, payload)),
+            None => this
+                .name_label
+                .set_label(&format!("\u{25C6} {}", mark.name)),
+        }
+
+        let time = match frame_start {
+            Some(frame_start) if mark.time >= frame_start => {
+                format!(
+                    "at +{:.2}ms",
+                    (mark.time - frame_start).as_secs_f64() * 1000.0
+                )
+            }
+            Some(frame_start) => {
+                format!(
+                    "at -{:.2}ms",
+                    (frame_start - mark.time).as_secs_f64() * 1000.0
+                )
+            }
+            None => format!("at {:.2}ms", mark.time.as_secs_f64() * 1000.0),
+        };
+        this.duration_label.set_label(&time);
+        self.set_tooltip_text(None);
+    }
 }
@@ -1,4 +1,5 @@
 use crate::frame_view::Task;
+use crate::mark_object::MarkObject;
 use crate::task_object::TaskObject;
 use gtk4::gio::{ListModel, ListStore};
 use gtk4::glib::Type;
@@ -31,9 +32,20 @@ impl TaskTree {
                 .unwrap();
             row_expander.set_list_row(Some(&row));
 
-            let task = row.item().unwrap().downcast::<TaskObject>().unwrap();
             let task_widget = row_expander.child().unwrap().downcast::<Task>().unwrap();
-            task_widget.set_task(Some(&task));
+            match row.item().unwrap().downcast::<TaskObject>() {
+                Ok(task) => task_widget.set_task(Some(&task)),
+                Err(item) => {
+                    // Marks are only listed under frames
+                    let mark = item.downcast::<MarkObject>().unwrap();
+                    let frame = row
+                        .parent()
+                        .and_then(|frame| frame.item())
+                        .and_then(|frame| frame.downcast::<TaskObject>().ok());
+                    let frame_start = frame.as_ref().and_then(|frame| frame.get().start);
+                    task_widget.set_mark(&mark, frame_start);
+                }
+            }
         });
         factory.connect_unbind(|_, list_item| {
             let row_expander = list_item
@@ -63,14 +75,19 @@ impl TaskTree {
                 let model = ListStore::new(Type::OBJECT);
                 model.append(&frame);
                 let model = TreeListModel::new(&model, false, false, |item| {
-                    let subtasks = &item.downcast_ref::<TaskObject>().unwrap().get().subtasks;
-                    if subtasks.is_empty() {
+                    let task = item.downcast_ref::<TaskObject>()?.get();
+                    if task.subtasks.is_empty() && task.marks.is_empty() {
                         return None;
                     }
-                    let mut subtasks = subtasks.clone();
+                    let mut subtasks = task.subtasks.clone();
                     subtasks.sort_by_key(|task| Reverse(task.duration));
 
+                    // List the frame's marks before its tasks, in the order they happened
                     let model = ListStore::new(Type::OBJECT);
+                    for mark in task.marks.iter() {
+                        let mark = MarkObject::new(mark.clone());
+                        model.append(&mark);
+                    }
                     for subtask in subtasks.iter() {
                         let subtask = TaskObject::new(subtask.clone());
                         model.append(&subtask);
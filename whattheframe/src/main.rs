@@ -1,5 +1,6 @@
 mod app_window;
 mod frame_view;
+mod mark_object;
 mod task_object;
 mod task_view;
 mod views;
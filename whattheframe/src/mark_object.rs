@@ -0,0 +1,45 @@
+use gtk4::glib::{self, Object};
+use gtk4::subclass::prelude::{ObjectImpl, ObjectSubclass, ObjectSubclassExt};
+use std::cell::{Ref, RefCell};
+use std::time::Duration;
+use wtf::Mark;
+
+mod inner {
+    use super::*;
+
+    pub struct MarkObject(pub RefCell<Mark>);
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for MarkObject {
+        const NAME: &'static str = "MarkObject";
+        type Type = super::MarkObject;
+        type ParentType = Object;
+
+        fn new() -> Self {
+            Self(RefCell::new(Mark {
+                name: "".into(),
+                time: Duration::default(),
+                thread: 0,
+                payload: None,
+            }))
+        }
+    }
+
+    impl ObjectImpl for MarkObject {}
+}
+
+glib::wrapper! {
+    pub struct MarkObject(ObjectSubclass<inner::MarkObject>);
+}
+
+impl MarkObject {
+    pub fn new(data: Mark) -> Self {
+        let obj = Object::new(&[]).unwrap();
+        *inner::MarkObject::from_instance(&obj).0.borrow_mut() = data;
+        obj
+    }
+
+    pub fn get(&self) -> Ref<Mark> {
+        inner::MarkObject::from_instance(self).0.borrow()
+    }
+}
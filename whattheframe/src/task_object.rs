@@ -1,7 +1,6 @@
 use gtk4::glib::{self, Object};
 use gtk4::subclass::prelude::{ObjectImpl, ObjectSubclass, ObjectSubclassExt};
 use std::cell::{Ref, RefCell};
-use std::time::Duration;
 use wtf::TaskData;
 
 mod inner {
@@ -16,11 +15,7 @@ mod inner {
         type ParentType = Object;
 
         fn new() -> Self {
-            Self(RefCell::new(TaskData {
-                name: Box::from(""),
-                duration: Duration::default(),
-                subtasks: Box::new([]),
-            }))
+            Self(RefCell::new(TaskData::default()))
         }
     }
 
@@ -2,19 +2,26 @@ use crate::frame_view::FrameView;
 use crate::task_object::TaskObject;
 use crate::task_view::TaskView;
 use gtk4::gio::{File, NONE_CANCELLABLE};
+use gtk4::glib::{self, MainContext, PRIORITY_DEFAULT};
 use gtk4::prelude::{BoxExt, FileExt, InputStreamExtManual, WidgetExt};
 use gtk4::{Align, Box as GtkBox, Label, ShortcutLabel, Stack, StackTransitionType};
 use libadwaita::StatusPage;
+use std::cell::Cell;
 use std::error::Error;
 use std::io::BufReader;
+use std::rc::Rc;
+use std::thread;
 use std::time::Duration;
-use wtf::read_profile_data;
+use wtf::{read_profile_data, Connection, ProfileReader, ServeAddress};
 
 pub struct Views {
     widget: Stack,
     views: Stack,
     frame_view: FrameView,
     task_view: TaskView,
+    /// Incremented whenever a profile is loaded or connected to, so that frames still arriving from a previous
+    /// connection are ignored
+    generation: Cell<u64>,
 }
 
 impl Views {
@@ -60,10 +67,12 @@ impl Views {
             views,
             frame_view,
             task_view,
+            generation: Cell::new(0),
         }
     }
 
     pub fn load_profile(&self, file: File) -> Result<&Stack, Box<dyn Error>> {
+        self.generation.set(self.generation.get() + 1);
         let file = file.read(NONE_CANCELLABLE)?.into_read();
         let file = BufReader::new(file);
         let tasks = read_profile_data(file)?
@@ -84,6 +93,62 @@ impl Views {
         Ok(&self.views)
     }
 
+    /// Connect to a running program, and add frames to the timeline as it sends them. Errors are passed to `on_error`
+    pub fn connect<F>(self: &Rc<Self>, address: ServeAddress, on_error: F) -> &Stack
+    where
+        F: Fn(String) + 'static,
+    {
+        self.generation.set(self.generation.get() + 1);
+        let generation = self.generation.get();
+        let address_label = address.to_string();
+
+        self.frame_view.load_frames(&[], 0);
+        self.task_view.load_tasks(&[]);
+        self.widget.set_visible_child_name("views");
+
+        // Frames are read on another thread, as reading waits for the program to finish each frame
+        let (sender, reciever) = MainContext::channel(PRIORITY_DEFAULT);
+        thread::spawn(move || {
+            let reader = Connection::connect(&address)
+                .map_err(wtf::Error::from)
+                .and_then(|connection| ProfileReader::new(BufReader::new(connection)));
+            match reader {
+                Ok(reader) => {
+                    for frame in reader {
+                        // Stop reading once the frames are no longer wanted
+                        if sender.send(frame.map_err(|err| err.to_string())).is_err() {
+                            break;
+                        }
+                    }
+                }
+                Err(err) => {
+                    let _ = sender.send(Err(err.to_string()));
+                }
+            }
+        });
+
+        reciever.attach(None, {
+            let views = Rc::clone(self);
+            move |frame| {
+                if views.generation.get() != generation {
+                    return glib::Continue(false);
+                }
+                match frame {
+                    Ok(frame) => {
+                        views.frame_view.append_frame(&TaskObject::new(frame));
+                        glib::Continue(true)
+                    }
+                    Err(err) => {
+                        on_error(format!("Connection to {} Failed: {}", address_label, err));
+                        glib::Continue(false)
+                    }
+                }
+            }
+        });
+
+        &self.views
+    }
+
     pub fn widget(&self) -> &Stack {
         &self.widget
     }
@@ -0,0 +1,82 @@
+//! Procedural macros for [wtf](https://docs.rs/wtf). Use them through the `wtf` crate, rather than depending on this crate directly.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Error, ItemFn, LitStr};
+
+/// Profile every call to a function, as if [`Profiler::profile_task`](https://docs.rs/wtf/latest/wtf/struct.Profiler.html#method.profile_task)
+/// was called at the top of it.
+///
+/// The task is named after the function's path, e.g. `my_game::physics::step`. Methods include the type they're implemented
+/// on, e.g. `my_game::physics::World::step`.
+/// To use a different name, pass it as an argument:
+/// ```rust,ignore
+/// #[wtf::profile("physics")]
+/// fn step() {}
+/// ```
+///
+/// `async fn`s can't be profiled, as the task would be held across `.await`s and could end on a different thread than it
+/// started on. Profile the code between `.await`s with [`profile_scope!`](https://docs.rs/wtf/latest/wtf/macro.profile_scope.html)
+/// instead. `const fn`s can't be profiled either.
+///
+/// When the `profile` feature is off, the function is left unchanged.
+#[proc_macro_attribute]
+pub fn profile(args: TokenStream, item: TokenStream) -> TokenStream {
+    let name = if args.is_empty() {
+        None
+    } else {
+        Some(parse_macro_input!(args as LitStr))
+    };
+    let function = parse_macro_input!(item as ItemFn);
+
+    // Checked even when the `profile` feature is off, so that enabling it can't break the build
+    if let Some(asyncness) = &function.sig.asyncness {
+        return Error::new_spanned(
+            asyncness,
+            "#[wtf::profile] can't be used on async functions, use wtf::profile_scope!() between .awaits instead",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if let Some(constness) = &function.sig.constness {
+        return Error::new_spanned(
+            constness,
+            "#[wtf::profile] can't be used on const functions",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    if cfg!(feature = "profile") {
+        let ItemFn {
+            attrs,
+            vis,
+            sig,
+            block,
+        } = function;
+        let name = match name {
+            Some(name) => quote! { #name },
+            // module_path!() doesn't include the type a method is implemented on, but the path of an item nested in the
+            // function does
+            None => quote! {{
+                fn _wtf_profile_marker() {}
+                fn type_name_of<T>(_: T) -> &'static str {
+                    ::std::any::type_name::<T>()
+                }
+                let name = type_name_of(_wtf_profile_marker);
+                name.strip_suffix("::_wtf_profile_marker").unwrap_or(name)
+            }},
+        };
+        let statements = block.stmts;
+
+        quote! {
+            #(#attrs)* #vis #sig {
+                let _wtf_profile = ::wtf::Profiler::profile_task(#name);
+                #(#statements)*
+            }
+        }
+        .into()
+    } else {
+        quote! { #function }.into()
+    }
+}
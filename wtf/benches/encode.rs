@@ -0,0 +1,117 @@
+//! Measures how fast profiles are written and read, and how large they are.
+//!
+//! Run with `cargo bench --bench encode`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use snap::write::FrameEncoder;
+use std::io::Write;
+use std::time::Duration;
+use wtf::{ProfileWriter, TaskData};
+
+const FRAMES: u64 = 1000;
+
+/// Frames shaped like a game's: a few systems per frame, each with a handful of named subtasks from a small set of names
+fn frames() -> Vec<TaskData> {
+    let systems = ["physics", "ai", "render", "audio", "scripts"];
+    (0..FRAMES)
+        .map(|frame| {
+            let frame_start = Duration::from_micros(frame * 16_667);
+            let mut start = frame_start;
+            let subtasks = systems
+                .iter()
+                .enumerate()
+                .map(|(system, name)| {
+                    let subtasks = (0..8)
+                        .map(|task| {
+                            let task = TaskData {
+                                name: format!("{}::task_{}", name, task).into(),
+                                start: Some(start),
+                                duration: Duration::from_nanos(
+                                    20_000 + (frame * 37 + task) % 5_000,
+                                ),
+                                thread: system as u64 % 3,
+                                ..Default::default()
+                            };
+                            start += task.duration;
+                            task
+                        })
+                        .collect::<Vec<_>>();
+                    TaskData {
+                        name: (*name).into(),
+                        start: subtasks[0].start,
+                        duration: subtasks.iter().map(|task| task.duration).sum(),
+                        thread: system as u64 % 3,
+                        subtasks: subtasks.into(),
+                        ..Default::default()
+                    }
+                })
+                .collect::<Vec<_>>();
+            TaskData {
+                name: format!("Frame #{}", frame + 1).into(),
+                start: Some(frame_start),
+                duration: start - frame_start,
+                thread: 0,
+                subtasks: subtasks.into(),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+fn write(frames: &[TaskData]) -> Vec<u8> {
+    let mut writer = ProfileWriter::new(Vec::new()).unwrap();
+    for frame in frames {
+        writer.write_frame(frame).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+/// Write a version 1 profile, as the crate wrote before version 2: the header and each frame as a bincode `Record::Frame`
+/// of a [`TaskData`], with fixed size integers. The frame index is left out, as it's written the same way in both versions
+fn write_v1(frames: &[TaskData]) -> Vec<u8> {
+    let mut profile = b"WTFPROF\0".to_vec();
+    profile.extend(1u32.to_le_bytes());
+    let mut encoder = FrameEncoder::new(profile);
+    // The header has no metadata
+    bincode::serialize_into(&mut encoder, &Vec::<(String, String)>::new()).unwrap();
+    for frame in frames {
+        // 0 is the variant index of `Record::Frame`
+        bincode::serialize_into(&mut encoder, &(0u32, frame)).unwrap();
+    }
+    encoder.flush().unwrap();
+    encoder.into_inner().unwrap()
+}
+
+fn encode(c: &mut Criterion) {
+    let frames = frames();
+    let tasks = FRAMES * (1 + 5 * 9);
+    let profiles = [("v1", write_v1(&frames)), ("v2", write(&frames))];
+    for (version, profile) in &profiles {
+        println!(
+            "Format {} profile of {} frames ({} tasks) is {} bytes, {:.1} bytes per task",
+            version,
+            FRAMES,
+            tasks,
+            profile.len(),
+            profile.len() as f64 / tasks as f64
+        );
+    }
+
+    let mut group = c.benchmark_group("encode");
+    group.throughput(Throughput::Elements(tasks));
+    group.bench_function("write v1", |b| b.iter(|| write_v1(&frames)));
+    group.bench_function("write v2", |b| b.iter(|| write(&frames)));
+    for (version, profile) in &profiles {
+        group.bench_function(format!("read {}", version), |b| {
+            b.iter_batched(
+                || profile.clone(),
+                |profile| wtf::read_profile_data(&profile[..]).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode);
+criterion_main!(benches);
@@ -0,0 +1,106 @@
+//! Measures the overhead of profiling a scope.
+//!
+//! Run with `cargo bench --features profile`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::env;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+use wtf::{Profiler, ProfilerConfig};
+
+/// The number of tasks per frame. Frames are kept small, so that the profiler doesn't hold too many tasks in memory.
+const TASKS_PER_FRAME: u64 = 1000;
+
+fn setup() {
+    let _ = Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(env::temp_dir().join("wtf-bench"))
+            .capture_on_start(false),
+    );
+}
+
+/// Time calling `f` `iterations` times, split across frames
+fn time_in_frames(iterations: u64, mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    let mut remaining = iterations;
+    while remaining > 0 {
+        let _frame = Profiler::new_frame();
+        for _ in 0..remaining.min(TASKS_PER_FRAME) {
+            f();
+        }
+        remaining = remaining.saturating_sub(TASKS_PER_FRAME);
+    }
+    start.elapsed()
+}
+
+fn single_thread(c: &mut Criterion) {
+    setup();
+    Profiler::start_capture().unwrap();
+
+    c.bench_function("profile_task", |b| {
+        b.iter_custom(|iterations| {
+            time_in_frames(iterations, || {
+                let _profile = Profiler::profile_task("task");
+            })
+        })
+    });
+
+    c.bench_function("profile_task nested", |b| {
+        b.iter_custom(|iterations| {
+            time_in_frames(iterations, || {
+                let _outer = Profiler::profile_task("outer");
+                let _inner = Profiler::profile_task("inner");
+            })
+        })
+    });
+
+    c.bench_function("profile_task_dyn", |b| {
+        b.iter_custom(|iterations| {
+            time_in_frames(iterations, || {
+                let _profile = Profiler::profile_task_dyn("task");
+            })
+        })
+    });
+
+    Profiler::stop_capture();
+
+    c.bench_function("profile_task not capturing", |b| {
+        b.iter(|| {
+            let _profile = Profiler::profile_task("task");
+        })
+    });
+}
+
+fn contended(c: &mut Criterion) {
+    setup();
+    Profiler::start_capture().unwrap();
+
+    const THREADS: usize = 4;
+    c.bench_function("profile_task 4 threads", |b| {
+        b.iter_custom(|iterations| {
+            let barrier = Arc::new(Barrier::new(THREADS));
+            let threads = (0..THREADS)
+                .map(|_| {
+                    let barrier = Arc::clone(&barrier);
+                    thread::spawn(move || {
+                        barrier.wait();
+                        time_in_frames(iterations, || {
+                            let _profile = Profiler::profile_task("task");
+                        })
+                    })
+                })
+                .collect::<Vec<_>>();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .sum::<Duration>()
+                / THREADS as u32
+        })
+    });
+
+    Profiler::stop_capture();
+}
+
+criterion_group!(benches, single_thread, contended);
+criterion_main!(benches);
@@ -0,0 +1,281 @@
+use crate::ServeAddress;
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Configures where profiles are saved, and how they're written.
+///
+/// Pass it to [`Profiler::configure`](crate::Profiler::configure) before using any other profiler function.
+///
+/// The file name is a template, where the following placeholders are replaced:
+/// * `{exe}` - The file name of the program being profiled
+/// * `{timestamp}` - The time the capture started, in the format `YYYY-MM-DD-HH-MM-SS`
+/// * `{capture}` - The number of the capture, starting at 1
+/// * `{label}` - The label set with [`ProfilerConfig::label`], or nothing if there isn't one
+/// * `{pid}` - The ID of the process being profiled
+/// * `{dump}` - The number of the dump, starting at 1, for profiles saved from [`ProfilerConfig::keep_recent`], or nothing otherwise
+///
+/// If you start more than one capture, the file name should include `{capture}`, or else captures may overwrite each other.
+/// Dumps of recent frames are always numbered: if the file name doesn't include `{dump}`, `-{dump}` is added before the extension.
+#[derive(Clone, Debug)]
+#[cfg_attr(not(feature = "profile"), allow(dead_code))]
+pub struct ProfilerConfig {
+    pub(crate) output_dir: PathBuf,
+    pub(crate) file_name: String,
+    pub(crate) label: Option<String>,
+    pub(crate) flush_interval: Duration,
+    pub(crate) capture_on_start: bool,
+    pub(crate) max_buffered_events: Option<usize>,
+    pub(crate) overflow_policy: OverflowPolicy,
+    pub(crate) serve: Option<ServeAddress>,
+    pub(crate) keep_recent: Option<KeepRecent>,
+    pub(crate) dump_slow_frames: Option<Duration>,
+    pub(crate) dump_frames_after: usize,
+}
+
+/// What happens when the profiler has buffered as many events as allowed by [`ProfilerConfig::max_buffered_events`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Threads wait for the data writing thread to catch up. Nothing is lost, but the program being profiled slows down.
+    Block,
+    /// New tasks are dropped until there's room for them again. Frames that lost tasks are marked as incomplete.
+    DropEvents,
+    /// Like [`OverflowPolicy::DropEvents`], but every task in a frame that lost tasks is dropped, so that incomplete frames
+    /// don't appear to be faster than they were.
+    DropFrames,
+}
+
+/// How many frames to keep in memory with [`ProfilerConfig::keep_recent`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeepRecent {
+    /// Keep the last number of frames. At least 1 frame is always kept.
+    Frames(usize),
+    /// Keep the frames that started within this long of the end of the last frame.
+    Time(Duration),
+}
+
+impl ProfilerConfig {
+    /// The default configuration, which saves profiles as `{exe}-{timestamp}-{capture}.wtf` in the current directory,
+    /// flushes after every frame, starts capturing when profiling starts, and doesn't limit memory use.
+    pub fn new() -> Self {
+        Self {
+            output_dir: PathBuf::from("."),
+            file_name: "{exe}-{timestamp}-{capture}.wtf".to_string(),
+            label: None,
+            flush_interval: Duration::default(),
+            capture_on_start: true,
+            max_buffered_events: None,
+            overflow_policy: OverflowPolicy::Block,
+            serve: None,
+            keep_recent: None,
+            dump_slow_frames: None,
+            dump_frames_after: 0,
+        }
+    }
+
+    /// The default configuration, overridden by the following environment variables if they're set:
+    /// * `WTF_OUTPUT_DIR` - See [`ProfilerConfig::output_dir`]
+    /// * `WTF_OUTPUT_FILE` - See [`ProfilerConfig::file_name`]
+    /// * `WTF_LABEL` - See [`ProfilerConfig::label`]
+    /// * `WTF_FLUSH_INTERVAL_MS` - See [`ProfilerConfig::flush_interval`], in milliseconds
+    /// * `WTF_CAPTURE` - See [`ProfilerConfig::capture_on_start`], `0` or `false` to disable
+    /// * `WTF_MAX_BUFFERED_EVENTS` - See [`ProfilerConfig::max_buffered_events`]
+    /// * `WTF_OVERFLOW_POLICY` - See [`ProfilerConfig::overflow_policy`], one of `block`, `drop-events`, or `drop-frames`
+    /// * `WTF_SERVE` - See [`ProfilerConfig::serve`], in the format described by [`ServeAddress`]
+    /// * `WTF_KEEP_RECENT_FRAMES` - See [`ProfilerConfig::keep_recent`], as a number of frames
+    /// * `WTF_KEEP_RECENT_MS` - See [`ProfilerConfig::keep_recent`], in milliseconds
+    /// * `WTF_DUMP_SLOW_FRAME_MS` - See [`ProfilerConfig::dump_slow_frames`], in milliseconds
+    /// * `WTF_DUMP_FRAMES_AFTER` - See [`ProfilerConfig::dump_frames_after`]
+    pub fn from_env() -> Self {
+        let mut config = Self::new();
+        if let Some(output_dir) = env::var_os("WTF_OUTPUT_DIR") {
+            config = config.output_dir(output_dir);
+        }
+        if let Ok(file_name) = env::var("WTF_OUTPUT_FILE") {
+            config = config.file_name(file_name);
+        }
+        if let Ok(label) = env::var("WTF_LABEL") {
+            config = config.label(label);
+        }
+        if let Some(flush_interval) = env::var("WTF_FLUSH_INTERVAL_MS")
+            .ok()
+            .and_then(|interval| interval.parse().ok())
+        {
+            config = config.flush_interval(Duration::from_millis(flush_interval));
+        }
+        if let Ok(capture) = env::var("WTF_CAPTURE") {
+            config = config.capture_on_start(!matches!(capture.as_str(), "0" | "false"));
+        }
+        if let Some(max_buffered_events) = env::var("WTF_MAX_BUFFERED_EVENTS")
+            .ok()
+            .and_then(|max| max.parse().ok())
+        {
+            config = config.max_buffered_events(max_buffered_events);
+        }
+        if let Ok(policy) = env::var("WTF_OVERFLOW_POLICY") {
+            match policy.as_str() {
+                "block" => config = config.overflow_policy(OverflowPolicy::Block),
+                "drop-events" => config = config.overflow_policy(OverflowPolicy::DropEvents),
+                "drop-frames" => config = config.overflow_policy(OverflowPolicy::DropFrames),
+                _ => {}
+            }
+        }
+        if let Some(address) = env::var("WTF_SERVE")
+            .ok()
+            .and_then(|address| address.parse::<ServeAddress>().ok())
+        {
+            config = config.serve(address);
+        }
+        if let Some(frames) = env::var("WTF_KEEP_RECENT_FRAMES")
+            .ok()
+            .and_then(|frames| frames.parse().ok())
+        {
+            config = config.keep_recent(KeepRecent::Frames(frames));
+        }
+        if let Some(time) = env::var("WTF_KEEP_RECENT_MS")
+            .ok()
+            .and_then(|time| time.parse().ok())
+        {
+            config = config.keep_recent(KeepRecent::Time(Duration::from_millis(time)));
+        }
+        if let Some(duration) = env::var("WTF_DUMP_SLOW_FRAME_MS")
+            .ok()
+            .and_then(|duration| duration.parse().ok())
+        {
+            config = config.dump_slow_frames(Duration::from_millis(duration));
+        }
+        if let Some(frames) = env::var("WTF_DUMP_FRAMES_AFTER")
+            .ok()
+            .and_then(|frames| frames.parse().ok())
+        {
+            config = config.dump_frames_after(frames);
+        }
+        config
+    }
+
+    /// The directory to save profiles in. It's created if it doesn't exist.
+    pub fn output_dir(mut self, output_dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = output_dir.into();
+        self
+    }
+
+    /// The template for the profile's file name. See [`ProfilerConfig`] for the available placeholders.
+    pub fn file_name(mut self, file_name: impl Into<String>) -> Self {
+        self.file_name = file_name.into();
+        self
+    }
+
+    /// A label for the profiling session, such as the level being tested. It's saved in the profile's header.
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// The minimum time between flushing the profile to disk.
+    ///
+    /// By default this is zero, so the profile is flushed after every frame.
+    /// This means as little data as possible is lost if the program crashes, at the cost of a larger file.
+    pub fn flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    /// Whether to start capturing as soon as profiling starts. Defaults to true.
+    ///
+    /// If false, nothing is recorded until [`Profiler::start_capture`](crate::Profiler::start_capture) is called.
+    pub fn capture_on_start(mut self, capture_on_start: bool) -> Self {
+        self.capture_on_start = capture_on_start;
+        self
+    }
+
+    /// Limit how many events are kept in memory before being written, roughly. By default there's no limit.
+    ///
+    /// Each task is 2 events, one when it starts and one when it ends. Events are sent to the data writing thread in batches,
+    /// so the limit is rounded up to a whole number of batches. What happens when the limit is reached depends on the
+    /// [`ProfilerConfig::overflow_policy`].
+    ///
+    /// With [`OverflowPolicy::Block`], only the events waiting to be processed are limited, not the tasks of the frame in progress.
+    pub fn max_buffered_events(mut self, max_buffered_events: usize) -> Self {
+        self.max_buffered_events = Some(max_buffered_events);
+        self
+    }
+
+    /// What to do when the [`ProfilerConfig::max_buffered_events`] limit is reached. Defaults to [`OverflowPolicy::Block`].
+    ///
+    /// The number of tasks dropped is available from [`Profiler::dropped_tasks`](crate::Profiler::dropped_tasks),
+    /// and saved in the profile for each frame as [`TaskData::dropped_tasks`](crate::TaskData::dropped_tasks).
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Serve frames to viewers such as WhatTheFrame while capturing, as each frame finishes. By default frames are only saved to files.
+    ///
+    /// Viewers connect with [`Connection::connect`](crate::Connection::connect). A viewer that connects while not capturing
+    /// waits for the next capture, and its connection is closed when the capture stops. Frames are still saved to files as usual.
+    ///
+    /// Each viewer is sent frames from a thread of its own. A viewer that falls about a second of frames behind is disconnected,
+    /// so a slow viewer can't slow down saving frames.
+    ///
+    /// Serving starts when profiling starts, and stops when profiling ends, removing the Unix socket if there is one.
+    /// If the address can't be listened on, an error is printed and profiling continues without it.
+    pub fn serve(mut self, address: impl Into<ServeAddress>) -> Self {
+        self.serve = Some(address.into());
+        self
+    }
+
+    /// Keep only the most recent frames in memory while capturing, instead of writing every frame to a file, like a flight recorder.
+    ///
+    /// Useful for hunting rare hitches in long sessions. The frames are saved to a new profile when
+    /// [`Profiler::dump_recent`](crate::Profiler::dump_recent) is called, or automatically after a frame slower than
+    /// [`ProfilerConfig::dump_slow_frames`]. Frames that are never dumped are discarded when the capture stops.
+    pub fn keep_recent(mut self, keep_recent: KeepRecent) -> Self {
+        self.keep_recent = Some(keep_recent);
+        self
+    }
+
+    /// Dump the frames kept by [`ProfilerConfig::keep_recent`] whenever a frame takes longer than this, such as 16.67ms for 60fps.
+    ///
+    /// Each dump is saved to its own file, ending with the slow frame and the [`ProfilerConfig::dump_frames_after`] after it,
+    /// and tagged with the slow frame's name as the `slow_frame` metadata. Frames are only dumped automatically once, so a dump
+    /// soon after another starts where the previous one ended, and a slow frame among the frames after another extends that dump
+    /// instead of starting a new one. Does nothing unless [`ProfilerConfig::keep_recent`] is set.
+    pub fn dump_slow_frames(mut self, duration: Duration) -> Self {
+        self.dump_slow_frames = Some(duration);
+        self
+    }
+
+    /// The number of frames after a slow frame to include in its dump. See [`ProfilerConfig::dump_slow_frames`]. Defaults to 0.
+    pub fn dump_frames_after(mut self, frames: usize) -> Self {
+        self.dump_frames_after = frames;
+        self
+    }
+
+    /// Only save the frames around hitches: whenever a frame takes longer than `threshold`, the `frames_before` it, the slow frame,
+    /// and the `frames_after` it are saved to a new file.
+    ///
+    /// Shorthand for [`ProfilerConfig::keep_recent`], [`ProfilerConfig::dump_slow_frames`], and [`ProfilerConfig::dump_frames_after`].
+    ///
+    /// ```rust,ignore
+    /// // Save 2 seconds of context around each frame slower than 60fps
+    /// let config = ProfilerConfig::new()
+    ///     .file_name("{exe}-{timestamp}-hitch-{dump}.wtf")
+    ///     .capture_hitches(Duration::from_micros(16_667), 120, 60);
+    /// ```
+    pub fn capture_hitches(
+        self,
+        threshold: Duration,
+        frames_before: usize,
+        frames_after: usize,
+    ) -> Self {
+        self.keep_recent(KeepRecent::Frames(frames_before + 1))
+            .dump_slow_frames(threshold)
+            .dump_frames_after(frames_after)
+    }
+}
+
+impl Default for ProfilerConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,81 @@
+use std::error::Error as StdError;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+/// An error that occurred while reading a profile.
+///
+/// Frame indices start at 0, and byte offsets are relative to the start of the (compressed) profile.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Reading from the underlying reader failed.
+    Io(io::Error),
+    /// The data does not start like a `.wtf` profile.
+    NotAProfile,
+    /// The profile was written with a format version this version of the crate doesn't know how to read.
+    UnsupportedVersion { version: u32 },
+    /// The header following the format version could not be read.
+    CorruptHeader,
+    /// The data ended partway through a frame, usually because the profiled program exited without calling
+    /// [`Profiler::end_profiling`](crate::Profiler::end_profiling).
+    TruncatedFrame { frame: usize, offset: u64 },
+    /// The compressed data for a frame is invalid.
+    Decompression {
+        frame: usize,
+        offset: u64,
+        source: io::Error,
+    },
+    /// A frame decompressed successfully, but its contents are invalid.
+    CorruptFrame {
+        frame: usize,
+        offset: u64,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "Failed to read profile: {}", err),
+            Error::NotAProfile => write!(f, "Not a WhatTheFrame profile"),
+            Error::UnsupportedVersion { version } => write!(
+                f,
+                "Profile has format version {}, but only versions up to {} are supported",
+                version,
+                crate::format::VERSION
+            ),
+            Error::CorruptHeader => write!(f, "Profile header is corrupt"),
+            Error::TruncatedFrame { frame, offset } => write!(
+                f,
+                "Profile ends partway through frame #{} (byte {})",
+                frame + 1,
+                offset
+            ),
+            Error::Decompression { frame, offset, .. } => write!(
+                f,
+                "Failed to decompress frame #{} (byte {})",
+                frame + 1,
+                offset
+            ),
+            Error::CorruptFrame { frame, offset, .. } => {
+                write!(f, "Frame #{} is corrupt (byte {})", frame + 1, offset)
+            }
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io(err) | Error::Decompression { source: err, .. } => Some(err),
+            Error::CorruptFrame { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
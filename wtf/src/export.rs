@@ -0,0 +1,205 @@
+//! Converting profiles to the formats of other tools.
+
+use crate::{ArgValue, Mark, ProfileData, TaskData};
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Write a profile as [Chrome Trace Event](https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU)
+/// JSON, which can be opened in `chrome://tracing` or [Perfetto](https://ui.perfetto.dev) alongside other traces.
+///
+/// Each frame and task becomes a complete (`"X"`) event. Tasks are shown on a track for the thread they ran on, with their args,
+/// and frames on a track of their own, with their counters as args. Marks become instant (`"i"`) events on the track for the
+/// thread they were recorded on, with their payload as an arg. Frames and tasks without a start time, such as those in
+/// profiles from older versions of WTF, are placed right after the previous frame or task at the same level.
+///
+/// `writer` should be buffered, e.g. with a [`BufWriter`](std::io::BufWriter).
+///
+/// ```rust,ignore
+/// let profile = read_profile_data(BufReader::new(File::open("game.wtf")?))?;
+/// wtf::export::chrome_trace(&profile, BufWriter::new(File::create("game.json")?))?;
+/// ```
+pub fn chrome_trace(profile: &ProfileData, mut writer: impl Write) -> io::Result<()> {
+    let mut threads = BTreeSet::new();
+    for frame in profile.iter() {
+        collect_threads(&frame.subtasks, &mut threads);
+        threads.extend(frame.marks.iter().map(|mark| mark.thread));
+    }
+    // Frames get a track after every thread's, sorted above them
+    let frames_track = threads.iter().next_back().map_or(0, |thread| thread + 1);
+
+    writer.write_all(b"{\"traceEvents\":[")?;
+    let mut events = EventWriter {
+        writer,
+        first: true,
+    };
+
+    events.thread_name(frames_track, "Frames")?;
+    events.thread_sort_index(frames_track, -1)?;
+    for &thread in &threads {
+        events.thread_name(thread, &format!("Thread {}", thread))?;
+    }
+
+    let mut next_start = Duration::default();
+    for frame in profile.iter() {
+        let start = frame.start.unwrap_or(next_start);
+        let mut args = frame
+            .counters
+            .iter()
+            .map(|(name, value)| (&**name, ArgValue::Float(*value)))
+            .collect::<Vec<_>>();
+        if frame.dropped_tasks > 0 {
+            args.push(("dropped_tasks", ArgValue::from(frame.dropped_tasks)));
+        }
+        events.complete(frame, start, frames_track, &args)?;
+        events.tasks(&frame.subtasks, start)?;
+        for mark in frame.marks.iter() {
+            events.instant(mark)?;
+        }
+        next_start = start + frame.duration;
+    }
+
+    let mut writer = events.writer;
+    writer.write_all(b"\n],\"displayTimeUnit\":\"ms\"}\n")?;
+    writer.flush()
+}
+
+fn collect_threads(tasks: &[TaskData], threads: &mut BTreeSet<u64>) {
+    for task in tasks {
+        threads.insert(task.thread);
+        collect_threads(&task.subtasks, threads);
+    }
+}
+
+struct EventWriter<W: Write> {
+    writer: W,
+    /// Whether no events have been written yet, as events after the first are preceded by a comma
+    first: bool,
+}
+
+impl<W: Write> EventWriter<W> {
+    /// Write the start of an event, up to and including its `pid`
+    fn start_event(&mut self, name: &str, phase: &str) -> io::Result<()> {
+        let separator = if self.first { "\n" } else { ",\n" };
+        self.first = false;
+        write!(self.writer, "{}{{\"name\":", separator)?;
+        write_string(&mut self.writer, name)?;
+        write!(self.writer, ",\"ph\":\"{}\",\"pid\":1", phase)
+    }
+
+    fn thread_name(&mut self, thread: u64, name: &str) -> io::Result<()> {
+        self.start_event("thread_name", "M")?;
+        write!(self.writer, ",\"tid\":{},\"args\":{{\"name\":", thread)?;
+        write_string(&mut self.writer, name)?;
+        self.writer.write_all(b"}}")
+    }
+
+    fn thread_sort_index(&mut self, thread: u64, index: i64) -> io::Result<()> {
+        self.start_event("thread_sort_index", "M")?;
+        write!(
+            self.writer,
+            ",\"tid\":{},\"args\":{{\"sort_index\":{}}}}}",
+            thread, index
+        )
+    }
+
+    /// Write a task as a complete event starting at `start`, on the track `thread`
+    fn complete(
+        &mut self,
+        task: &TaskData,
+        start: Duration,
+        thread: u64,
+        args: &[(&str, ArgValue)],
+    ) -> io::Result<()> {
+        self.start_event(&task.name, "X")?;
+        write!(
+            self.writer,
+            ",\"tid\":{},\"ts\":{},\"dur\":{}",
+            thread,
+            Micros(start),
+            Micros(task.duration)
+        )?;
+        if !args.is_empty() {
+            self.writer.write_all(b",\"args\":{")?;
+            for (i, (key, value)) in args.iter().enumerate() {
+                if i > 0 {
+                    self.writer.write_all(b",")?;
+                }
+                write_string(&mut self.writer, key)?;
+                self.writer.write_all(b":")?;
+                match value {
+                    ArgValue::Int(value) => write!(self.writer, "{}", value)?,
+                    ArgValue::Float(value) if value.is_finite() => {
+                        write!(self.writer, "{}", value)?
+                    }
+                    // JSON has no infinity or NaN
+                    ArgValue::Float(value) => write_string(&mut self.writer, &value.to_string())?,
+                    ArgValue::String(value) => write_string(&mut self.writer, value)?,
+                    ArgValue::Bool(value) => write!(self.writer, "{}", value)?,
+                }
+            }
+            self.writer.write_all(b"}")?;
+        }
+        self.writer.write_all(b"}")
+    }
+
+    /// Write a mark as an instant event, scoped to the track of the thread it was recorded on
+    fn instant(&mut self, mark: &Mark) -> io::Result<()> {
+        self.start_event(&mark.name, "i")?;
+        write!(
+            self.writer,
+            ",\"tid\":{},\"ts\":{},\"s\":\"t\"",
+            mark.thread,
+            Micros(mark.time)
+        )?;
+        if let Some(payload) = &mark.payload {
+            self.writer.write_all(b",\"args\":{\"payload\":")?;
+            write_string(&mut self.writer, payload)?;
+            self.writer.write_all(b"}")?;
+        }
+        self.writer.write_all(b"}")
+    }
+
+    /// Write tasks and their subtasks, placing those without a start time after the previous task, or at `parent_start`
+    fn tasks(&mut self, tasks: &[TaskData], parent_start: Duration) -> io::Result<()> {
+        let mut next_start = parent_start;
+        for task in tasks {
+            let start = task.start.unwrap_or(next_start);
+            let args = task
+                .args
+                .iter()
+                .map(|(key, value)| (&**key, value.clone()))
+                .collect::<Vec<_>>();
+            self.complete(task, start, task.thread, &args)?;
+            self.tasks(&task.subtasks, start)?;
+            next_start = start + task.duration;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a duration in microseconds, the unit of trace event times, without losing any nanoseconds
+struct Micros(Duration);
+
+impl std::fmt::Display for Micros {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let nanos = self.0.as_nanos();
+        write!(f, "{}.{:03}", nanos / 1000, nanos % 1000)
+    }
+}
+
+fn write_string<W: Write>(writer: &mut W, string: &str) -> io::Result<()> {
+    writer.write_all(b"\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => writer.write_all(b"\\\"")?,
+            '\\' => writer.write_all(b"\\\\")?,
+            '\n' => writer.write_all(b"\\n")?,
+            '\r' => writer.write_all(b"\\r")?,
+            '\t' => writer.write_all(b"\\t")?,
+            c if c < ' ' => write!(writer, "\\u{:04x}", c as u32)?,
+            c => write!(writer, "{}", c)?,
+        }
+    }
+    writer.write_all(b"\"")
+}
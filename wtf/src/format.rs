@@ -0,0 +1,199 @@
+//! The on-disk layout of `.wtf` profiles.
+//!
+//! A profile starts with [`MAGIC`] followed by the format version as a little-endian `u32`.
+//! The rest of the file is a snappy frame stream containing a bincode [`Header`], followed by any number of bincode [`Record`]s.
+//!
+//! Since version 2, records are encoded with [`record_options`], which writes integers as varints, and frames are [`CompactFrame`]s.
+//! Task names are stored once in a string table: a [`Record::Name`] is written before the first frame that uses a name,
+//! and tasks refer to it by its id. Frame names are usually unique, so they're stored in the frame instead. Version 1 profiles store frames as bincode [`TaskData`]s with fixed size integers.
+//!
+//! Profiles written before the header existed (version 0) are a bare snappy frame stream of bincode [`TaskDataV0`]s.
+//!
+//! When a capture is stopped, a frame index is appended to the snappy frame stream using chunk types that decoders skip:
+//! one or more [`INDEX_CHUNK_TYPE`] chunks holding a [`FrameOffset`] per frame, followed by a single [`INDEX_TRAILER_CHUNK_TYPE`]
+//! chunk at the very end of the file, holding [`INDEX_TRAILER_MAGIC`], the file offset of the first index chunk, and the number of frames.
+//! All numbers are little-endian `u64`s. In version 2 and later, the index chunks are preceded by [`INDEX_NAMES_CHUNK_TYPE`] chunks
+//! holding the string table, and [`INDEX_METADATA_CHUNK_TYPE`] chunks holding the metadata from [`Record::Metadata`]s,
+//! so that frames can be read without reading the records before them.
+
+use crate::{ArgValue, TaskData};
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::time::Duration;
+
+pub const MAGIC: [u8; 8] = *b"WTFPROF\0";
+
+/// The format version written by this version of the crate.
+pub const VERSION: u32 = 2;
+
+/// The first bytes of a snappy frame stream, used to detect version 0 profiles.
+pub const SNAPPY_STREAM_IDENTIFIER: [u8; 8] = *b"\xFF\x06\x00\x00sNaP";
+
+/// The complete stream identifier chunk, which a snappy decoder must read before any other chunk.
+pub const SNAPPY_STREAM_HEADER: [u8; 10] = *b"\xFF\x06\x00\x00sNaPpY";
+
+/// The snappy chunk type of chunks holding the frame index.
+pub const INDEX_CHUNK_TYPE: u8 = 0x80;
+
+/// The snappy chunk type of the chunk that locates the frame index.
+pub const INDEX_TRAILER_CHUNK_TYPE: u8 = 0x81;
+
+/// The snappy chunk type of chunks holding the string table, as consecutive [`record_options`] encoded strings ordered by id.
+/// A string may be split across chunks.
+pub const INDEX_NAMES_CHUNK_TYPE: u8 = 0x82;
+
+/// The snappy chunk type of chunks holding the metadata set after the header, as consecutive [`record_options`] encoded
+/// key/value pairs. A pair may be split across chunks.
+pub const INDEX_METADATA_CHUNK_TYPE: u8 = 0x83;
+
+pub const INDEX_TRAILER_MAGIC: [u8; 8] = *b"WTFINDEX";
+
+/// The size of the index trailer chunk, including its 4 byte chunk header.
+pub const INDEX_TRAILER_LEN: usize = 4 + INDEX_TRAILER_MAGIC.len() + 8 + 8;
+
+/// The maximum number of frame offsets in a single index chunk.
+///
+/// Snappy decoders reject chunks longer than a compressed block, even ones they skip, so index chunks are kept to 64 KiB.
+#[cfg_attr(not(feature = "profile"), allow(dead_code))]
+pub const INDEX_CHUNK_FRAMES: usize = (1 << 16) / 16;
+
+/// The maximum length of a single string table or metadata chunk, for the same reason as [`INDEX_CHUNK_FRAMES`].
+#[cfg_attr(not(feature = "profile"), allow(dead_code))]
+pub const INDEX_TABLE_CHUNK_LEN: usize = 1 << 16;
+
+/// Where a frame's records start.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameOffset {
+    /// The file offset of a snappy chunk at or before the start of the frame.
+    pub chunk: u64,
+    /// The number of decompressed bytes from the start of that chunk to the start of the frame.
+    pub skip: u64,
+}
+
+/// The 4 byte header of a snappy chunk: its type followed by its length as a little-endian 24 bit number.
+pub fn chunk_header(chunk_type: u8, len: usize) -> [u8; 4] {
+    let len = (len as u32).to_le_bytes();
+    [chunk_type, len[0], len[1], len[2]]
+}
+
+/// The bincode options used for records since version 2. The header and older versions use bincode's default fixed size integers.
+pub fn record_options() -> impl Options {
+    bincode::DefaultOptions::new()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Header {
+    /// Key/value pairs describing the capture, such as the program name and start time.
+    pub metadata: Vec<(String, String)>,
+}
+
+/// A single entry in the profile after the header.
+/// New kinds of records should be added as new variants, rather than changing existing ones.
+#[derive(Serialize, Deserialize)]
+pub enum Record<T> {
+    Frame(T),
+    /// The number of tasks dropped from the next frame, because the profiler reached its memory limit.
+    DroppedTasks(u64),
+    /// Adds a name to the string table. Ids start at 0 and count up by 1.
+    Name(u32, Box<str>),
+    /// Sets a metadata key after the header was written, replacing any previous value.
+    Metadata(String, String),
+    /// The value of a counter in the next frame. The counter's name is an id in the string table.
+    Counter(u32, f64),
+    /// A mark in the next frame.
+    Mark(CompactMark),
+    /// The args of a task in the next frame: the task's index in the frame, counting its tasks depth first and
+    /// not counting the frame itself, then each arg's name as an id in the string table and its value.
+    Args(u32, Vec<(u32, ArgValue)>),
+    /// The args of the next frame itself, like [`Record::Args`].
+    FrameArgs(Vec<(u32, ArgValue)>),
+}
+
+/// Set a metadata key, replacing any previous value.
+pub fn set_metadata(metadata: &mut Vec<(String, String)>, key: String, value: String) {
+    match metadata.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, v)) => *v = value,
+        None => metadata.push((key, value)),
+    }
+}
+
+/// A frame as stored since version 2. Times are in nanoseconds.
+#[derive(Deserialize)]
+pub struct CompactFrame {
+    pub name: Box<str>,
+    pub start: Option<u64>,
+    pub duration: u64,
+    pub thread: u64,
+    pub subtasks: Vec<CompactTask>,
+}
+
+/// A task as stored since version 2.
+///
+/// Times are in nanoseconds. A task's start is relative to its parent's start, or absolute if its parent has no start. Since a task's start is close to its parent's, it's usually only a few bytes as a varint.
+#[derive(Deserialize)]
+pub struct CompactTask {
+    /// An id in the string table
+    pub name: u32,
+    pub start: Option<i64>,
+    pub duration: u64,
+    pub thread: u64,
+    pub subtasks: Vec<Self>,
+}
+
+#[derive(Deserialize)]
+pub struct TaskDataV0 {
+    name: Box<str>,
+    duration: Duration,
+    subtasks: Box<[Self]>,
+}
+
+impl From<TaskDataV0> for TaskData {
+    fn from(task: TaskDataV0) -> Self {
+        Self {
+            name: task.name,
+            start: None,
+            duration: task.duration,
+            thread: 0,
+            dropped_tasks: 0,
+            counters: Box::default(),
+            marks: Box::default(),
+            args: Box::default(),
+            subtasks: task
+                .subtasks
+                .into_vec()
+                .into_iter()
+                .map(Self::from)
+                .collect(),
+        }
+    }
+}
+
+/// A [`Mark`](crate::Mark) as stored since version 2.
+#[derive(Serialize, Deserialize)]
+pub struct CompactMark {
+    /// An id in the string table
+    pub name: u32,
+    /// In nanoseconds, relative to the start of the frame it's in like a [`CompactTask`]'s start
+    pub time: i64,
+    pub thread: u64,
+    pub payload: Option<Box<str>>,
+}
+
+/// Encode a task's start for a [`CompactTask`].
+pub fn encode_start(start: Option<Duration>, parent_start: Option<Duration>) -> Option<i64> {
+    let start = start?.as_nanos() as i64;
+    Some(match parent_start {
+        Some(parent_start) => start - parent_start.as_nanos() as i64,
+        None => start,
+    })
+}
+
+/// Decode a task's start from a [`CompactTask`], or return `None` if it's before the start of the capture.
+pub fn decode_start(start: i64, parent_start: Option<Duration>) -> Option<Duration> {
+    let start = match parent_start {
+        Some(parent_start) => (parent_start.as_nanos() as i64).checked_add(start)?,
+        None => start,
+    };
+    u64::try_from(start).ok().map(Duration::from_nanos)
+}
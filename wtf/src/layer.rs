@@ -0,0 +1,175 @@
+use crate::{ArgValue, FrameProfilingReturnType, Profiler, TaskRecord};
+use std::fmt;
+use std::thread::{self, ThreadId};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// A [`Layer`] that profiles [`tracing`] spans, so that code already instrumented with `tracing` shows up in profiles
+/// without calling [`Profiler::profile_task`]. Requires the `tracing` feature.
+///
+/// Entering a span starts a task with the span's name, and exiting it ends the task. The span's fields become the task's args,
+/// like [`TaskRecord::arg`]. Spans named [`ProfilerLayer::frame_span`] start and end frames instead of tasks.
+///
+/// ```rust,ignore
+/// tracing_subscriber::registry()
+///     .with(ProfilerLayer::new().frame_span("frame"))
+///     .init();
+///
+/// loop {
+///     let _frame = tracing::info_span!("frame").entered();
+///     update_physics();
+///     render();
+/// }
+/// ```
+///
+/// Like [`Profiler::profile_task`], this does nothing unless the `profile` feature is enabled.
+#[derive(Clone, Debug, Default)]
+pub struct ProfilerLayer {
+    frame_span: Option<&'static str>,
+}
+
+impl ProfilerLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The name of the span that marks frames, as if entering it called [`Profiler::new_frame`].
+    ///
+    /// By default no span marks frames, so frames must be started with [`Profiler::new_frame`].
+    pub fn frame_span(mut self, name: &'static str) -> Self {
+        self.frame_span = Some(name);
+        self
+    }
+}
+
+/// The fields recorded for a span so far, stored in the span's extensions
+struct SpanArgs(Vec<(&'static str, ArgValue)>);
+
+impl SpanArgs {
+    fn set(&mut self, key: &'static str, value: ArgValue) {
+        match self.0.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, existing)) => *existing = value,
+            None => self.0.push((key, value)),
+        }
+    }
+}
+
+impl Visit for SpanArgs {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.set(field.name(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field.name(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.set(field.name(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field.name(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field.name(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.set(field.name(), format!("{:?}", value).into());
+    }
+}
+
+/// What was started each time a span was entered, and on which thread, stored in the span's extensions. Spans can be entered
+/// again before they're exited, on the same thread or on others at the same time, so this is a stack for each thread
+struct EnteredSpan(Vec<(ThreadId, Entered)>);
+
+enum Entered {
+    Task(TaskRecord),
+    /// Only held to be dropped when the span is exited
+    Frame {
+        _record: FrameProfilingReturnType,
+    },
+}
+
+impl<S> Layer<S> for ProfilerLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut args = SpanArgs(Vec::new());
+            attrs.record(&mut args);
+            span.extensions_mut().insert(args);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            let mut args = SpanArgs(Vec::new());
+            values.record(&mut args);
+
+            // Update the tasks already in progress too
+            if let Some(entered) = extensions.get_mut::<EnteredSpan>() {
+                for (_, entered) in &mut entered.0 {
+                    if let Entered::Task(task) = entered {
+                        for (key, value) in &args.0 {
+                            task.set_arg(key, value.clone());
+                        }
+                    }
+                }
+            }
+            if let Some(span_args) = extensions.get_mut::<SpanArgs>() {
+                for (key, value) in args.0 {
+                    span_args.set(key, value);
+                }
+            }
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            let entered = if self.frame_span == Some(span.name()) {
+                Entered::Frame {
+                    _record: Profiler::new_frame(),
+                }
+            } else {
+                let mut task = Profiler::profile_task(span.name());
+                if let Some(args) = extensions.get_mut::<SpanArgs>() {
+                    for (key, value) in &args.0 {
+                        task.set_arg(key, value.clone());
+                    }
+                }
+                Entered::Task(task)
+            };
+
+            let entered = (thread::current().id(), entered);
+            match extensions.get_mut::<EnteredSpan>() {
+                Some(entered_span) => entered_span.0.push(entered),
+                None => extensions.insert(EnteredSpan(vec![entered])),
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            // Dropping the record ends the task or frame. Only this thread's latest entry is exited, as other threads may be
+            // in the same span
+            let thread = thread::current().id();
+            if let Some(entered_span) = span.extensions_mut().get_mut::<EnteredSpan>() {
+                if let Some(i) = entered_span
+                    .0
+                    .iter()
+                    .rposition(|(entered, _)| *entered == thread)
+                {
+                    entered_span.0.remove(i);
+                }
+            }
+        }
+    }
+}
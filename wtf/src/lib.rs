@@ -28,16 +28,99 @@
 //! * [`Profiler::profile_task`] - Call at the top of each scope you want to profile
 //! * [`Profiler::end_profiling`] - Call _once_ at the end of your game
 //!
+//! And some optional extras:
+//! * [`Profiler::profile_task_dyn`] - Like [`Profiler::profile_task`], but for task names built at runtime
+//! * [`read_profile_data_lenient`] - Used to read the intact frames of a `.wtf` profile that was cut off, e.g. by a crash
+//! * [`ProfileReader`] - Used to read a `.wtf` profile one frame at a time, for profiles too large to load at once
+//! * [`Profile`] - Used to read any frame of a `.wtf` profile directly, without reading the frames before it
+//! * [`ProfileWriter`] - Used to write a `.wtf` profile from your own frames, e.g. to convert other trace formats
+//! * [`export::chrome_trace`] - Used to convert a `.wtf` profile to JSON for `chrome://tracing` or Perfetto, to view it alongside other traces
+//! * [`Profiler::mark`] - Record a moment in time, such as a level finishing loading, to see what was happening during a slow frame
+//! * [`TaskRecord::arg`] - Attach context to a task, such as which entity it updated, to see when hovering over the task
+//! * [`Profiler::counter`] - Record a number once per frame, such as the number of draw calls, to plot alongside frame times
+//! * [`Profiler::set_metadata`] - Record information about the run, such as your game's version, which can be read back with [`Profile::metadata`]
+//! * [`ProfilerConfig::serve`] - Stream frames to WhatTheFrame as your game runs, instead of opening the profile once it's saved
+//! * [`ProfilerConfig::keep_recent`] - Keep only the last few seconds of frames in memory, and save them with [`Profiler::dump_recent`] or after a slow frame
+//! * [`ProfilerConfig::capture_hitches`] - Only save the frames around slow frames, each hitch to its own small file
+//! * `ProfilerLayer` - With the `tracing` feature, a `tracing_subscriber` layer that profiles your existing `tracing` spans as tasks and frames
+//! * [`Profiler::install_panic_hook`] - Call at the start of your game, to save the profile if the main thread of your game panics
+//! * [`Profiler::configure`] - Call at the start of your game, to change where and when profiles are saved
+//! * [`Profiler::start_capture`], [`Profiler::stop_capture`], and [`Profiler::toggle_capture`] - Control when profiling data is recorded
+//!
+//! By default, the profile is saved to `<exe>-<timestamp>-1.wtf` in the current directory. See [`ProfilerConfig`] for how to
+//! change this, either through [`Profiler::configure`] or environment variables.
+//!
+//! The profile is flushed to disk after every frame, so that it survives your game crashing.
+//! To flush less often, set [`ProfilerConfig::flush_interval`].
+//!
+//! By default, the profiler keeps as much data in memory as it needs to, while waiting for it to be written.
+//! To limit memory use, set [`ProfilerConfig::max_buffered_events`] and an [`OverflowPolicy`].
+//! Frames that lost tasks because of the limit are marked as incomplete in the profile.
+//!
+//! Each thread buffers the events it records, and sends them to the data writing thread in batches, rather than one at a time.
+//! Measured with `cargo bench --features profile --bench overhead` on a single core machine, as the range over several runs:
+//!
+//! | Benchmark                  | Sent one at a time | Buffered per thread |
+//! |----------------------------|--------------------|---------------------|
+//! | profile_task               | 365-419 ns         | 354-443 ns          |
+//! | profile_task nested        | 686-789 ns         | 715-762 ns          |
+//! | profile_task_dyn           | 416-453 ns         | 417-421 ns          |
+//! | profile_task not capturing | 7.7-10.4 ns        | 10.2-10.6 ns        |
+//! | profile_task 4 threads     | 0.83-1.45 µs       | 1.08-1.14 µs        |
+//!
+//! With one core, the data writing thread competes with the benchmark for it, so the differences are within the noise.
+//!
+//! Profiles store each task name once, and times as variable length integers. Compared to the previous format version,
+//! measured with `cargo bench --bench encode` on 1000 frames of 46 tasks each:
+//!
+//! | Format version | File size | Bytes per task | Write time | Read time |
+//! |----------------|-----------|----------------|------------|-----------|
+//! | 1              | 643 KB    | 14.0           | 9.2 ms     | 15.7 ms   |
+//! | 2              | 272 KB    | 5.9            | 5.4 ms     | 13.1 ms   |
+//!
+//! The benchmark writes version 1 profiles without a frame index, which adds about 16 bytes per frame.
+//!
+//! Profiles written by older versions can still be read.
+//!
+//! # Macros
+//!
+//! Instead of calling [`Profiler::profile_task`] yourself, you can use:
+//! * [`profile`] - An attribute that profiles every call to a function, naming the task after the function's path
+//! * [`profile_scope!`] - Profiles the rest of the current scope, naming the task after the module and line number
+//!
+//! ```rust,ignore
+//! #[wtf::profile]
+//! fn update_physics() {
+//!     for body in bodies {
+//!         wtf::profile_scope!();
+//!         // ...
+//!     }
+//!     wtf::profile_scope!("collisions");
+//!     // ...
+//! }
+//! ```
+//!
+//! Both compile to nothing when the `profile` feature is off.
+//!
+//!
+//! # Capturing
+//!
+//! By default, recording starts the first time a profiler function is called, and lasts until [`Profiler::end_profiling`].
+//!
+//! To only record part of your game, set [`ProfilerConfig::capture_on_start`] to false (or the `WTF_CAPTURE` environment variable to `0`),
+//! and call [`Profiler::start_capture`] and [`Profiler::stop_capture`], e.g. from a debug hotkey.
+//! Each capture is saved to its own file. While not capturing, profiler functions do almost nothing.
+//!
 //! Note that you _must_ assign [`Profiler::new_frame`] and [`Profiler::profile_task`] to a variable (_not_ `_`) like so:
-//! ```rust
+//! ```rust,ignore
 //! let _profile = Profiler::new_frame();
 //! ```
-//! ```rust
-//! let _profile = Profiler::new_task("foo");
+//! ```rust,ignore
+//! let _profile = Profiler::profile_task("foo");
 //! ```
 //!
 //! Example with `winit`:
-//! ```rust
+//! ```rust,ignore
 //! use std::sync::atomic::{AtomicBool, Ordering};
 //! use std::sync::Arc;
 //! use std::thread;
@@ -100,228 +183,204 @@
 //! }
 //! ```
 
-use serde::Deserialize;
-use snap::read::FrameDecoder;
-use std::io::{self, Read};
-use std::time::Duration;
+mod config;
+mod error;
+pub mod export;
+mod format;
+#[cfg(feature = "tracing")]
+mod layer;
+mod profiler;
+mod read;
+mod stream;
+mod write;
 
-#[cfg(feature = "profile")]
-use {
-    chrono::offset::Utc,
-    flume::Sender,
-    once_cell::sync::Lazy,
-    serde::Serialize,
-    snap::write::FrameEncoder,
-    std::env,
-    std::fs::File,
-    std::io::Write,
-    std::mem,
-    std::sync::Mutex,
-    std::thread::{self, JoinHandle},
-    std::time::Instant,
-};
-
-#[cfg(feature = "profile")]
-static PROFILER: Lazy<Profiler> = Lazy::new(|| {
-    let (sender, reciever) = flume::unbounded();
-
-    let thread = thread::Builder::new()
-        .name("wtf-profiler".to_string())
-        .spawn(move || {
-            fn create_file() -> Option<FrameEncoder<File>> {
-                let program_name = env::current_exe().ok()?;
-                let program_name = program_name.file_name()?.to_str()?;
-                let timestamp = Utc::now().format("%F-%T");
-                let file = File::create(format!("{}-{}.wtf", program_name, timestamp)).ok()?;
-                Some(FrameEncoder::new(file))
-            }
-            let mut file = create_file().expect("WTF: Failed to create file for profile");
+pub use crate::config::*;
+pub use crate::error::*;
+#[cfg(feature = "tracing")]
+pub use crate::layer::*;
+pub use crate::profiler::*;
+pub use crate::read::*;
+pub use crate::stream::*;
+pub use crate::write::*;
+pub use wtf_macros::profile;
 
-            #[derive(Serialize)]
-            struct TaskDataS {
-                name: &'static str,
-                duration: Duration,
-                subtasks: Vec<Self>,
-            }
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::Duration;
 
-            let mut frame_number: usize = 0;
-            let mut frame = TaskDataS {
-                name: "",
-                duration: Duration::default(),
-                subtasks: Vec::new(),
-            };
-            let mut parent_stack: Vec<*mut TaskDataS> = vec![&mut frame];
+/// Profile the rest of the current scope.
+///
+/// With no arguments, the task is named after the module and line number, e.g. `my_game::physics:42`.
+/// Otherwise, the arguments are used as the name, either a string literal or a format string with arguments:
+/// ```rust,ignore
+/// wtf::profile_scope!();
+/// wtf::profile_scope!("physics");
+/// wtf::profile_scope!("load_level {}", level_name);
+/// ```
+///
+/// When the `profile` feature is off, this compiles to nothing, and the arguments are not evaluated.
+#[cfg(feature = "profile")]
+#[macro_export]
+macro_rules! profile_scope {
+    () => {
+        let _wtf_profile_scope =
+            $crate::Profiler::profile_task(concat!(module_path!(), ":", line!()));
+    };
+    ($name:literal) => {
+        let _wtf_profile_scope = $crate::Profiler::profile_task($name);
+    };
+    ($format:literal, $($arg:tt)*) => {
+        let _wtf_profile_scope = $crate::Profiler::profile_task_dyn(format!($format, $($arg)*));
+    };
+}
 
-            loop {
-                let msg = reciever.recv_timeout(Duration::from_millis(100));
-                match msg {
-                    Ok(ProfilerMessage::TaskStart { name }) => {
-                        // Create a new task with a placeholder duration
-                        let task = TaskDataS {
-                            name,
-                            duration: Duration::default(),
-                            subtasks: Vec::new(),
-                        };
+/// Profile the rest of the current scope.
+///
+/// With no arguments, the task is named after the module and line number, e.g. `my_game::physics:42`.
+/// Otherwise, the arguments are used as the name, either a string literal or a format string with arguments:
+/// ```rust,ignore
+/// wtf::profile_scope!();
+/// wtf::profile_scope!("physics");
+/// wtf::profile_scope!("load_level {}", level_name);
+/// ```
+///
+/// When the `profile` feature is off, this compiles to nothing, and the arguments are not evaluated.
+#[cfg(not(feature = "profile"))]
+#[macro_export]
+macro_rules! profile_scope {
+    ($($arg:tt)*) => {};
+}
 
-                        // Add it to the current parent's subtasks
-                        let parent = *parent_stack.last().unwrap();
-                        let parent_subtasks = unsafe { &mut (*parent).subtasks };
-                        parent_subtasks.push(task);
+pub type ProfileData = Box<[TaskData]>;
 
-                        // Push it to the top of the parent stack
-                        let task_ref = parent_subtasks.last_mut().unwrap();
-                        parent_stack.push(task_ref);
-                    }
-                    Ok(ProfilerMessage::TaskEnd { elapsed }) => {
-                        // Replace the placeholder with the real duration
-                        let task = *parent_stack.last().unwrap();
-                        unsafe { (*task).duration = elapsed }
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TaskData {
+    pub name: Box<str>,
+    /// When the task started, relative to the start of the capture
+    ///
+    /// This is `None` for profiles recorded before start times were tracked
+    pub start: Option<Duration>,
+    pub duration: Duration,
+    /// An ID unique to the thread the task ran on, assigned in the order threads first used the profiler
+    pub thread: u64,
+    /// For frames, the number of tasks dropped from the frame because the profiler reached its memory limit
+    ///
+    /// Frames where this isn't 0 are incomplete. It's always 0 for tasks that aren't frames
+    #[serde(skip)]
+    pub dropped_tasks: u64,
+    /// For frames, the value of each counter recorded during the frame with [`Profiler::counter`]
+    ///
+    /// It's always empty for tasks that aren't frames
+    #[serde(skip)]
+    pub counters: Box<[(Box<str>, f64)]>,
+    /// For frames, the marks recorded during the frame with [`Profiler::mark`], in the order they were recorded
+    ///
+    /// It's always empty for tasks that aren't frames
+    #[serde(skip)]
+    pub marks: Box<[Mark]>,
+    /// Key/value pairs attached to the task with [`TaskRecord::arg`], in the order they were first set
+    #[serde(skip)]
+    pub args: Box<[(Box<str>, ArgValue)]>,
+    pub subtasks: Box<[Self]>,
+}
 
-                        // If only 1 task left in the parent stack (the frame), then write the frame to the file
-                        // Else pop the parent stack
-                        if parent_stack.len() == 1 {
-                            frame_number += 1;
-                            let frame_name = format!("Frame #{}", frame_number);
-                            // SAFETY: frame.name is temporarily set to reference a local string
-                            // It must be reset to a valid reference by the end of the scope
-                            frame.name = unsafe { mem::transmute(frame_name.as_str()) };
+/// The value of a task argument, set with [`TaskRecord::arg`].
+///
+/// Integers, floats, bools, and strings convert into this, so it rarely needs to be named.
+/// Strings are stored in full, so keep them short.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ArgValue {
+    Int(i64),
+    Float(f64),
+    String(Box<str>),
+    Bool(bool),
+}
 
-                            bincode::serialize_into(&mut file, &frame)
-                                .expect("WTF: Failed to write data to file");
+impl fmt::Display for ArgValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgValue::Int(value) => value.fmt(f),
+            ArgValue::Float(value) => value.fmt(f),
+            ArgValue::String(value) => value.fmt(f),
+            ArgValue::Bool(value) => value.fmt(f),
+        }
+    }
+}
 
-                            // Reset for the next frame
-                            frame.name = "";
-                            frame.subtasks.clear();
-                        } else {
-                            parent_stack.pop().unwrap();
-                        }
-                    }
-                    _ => {
-                        // Haven't recieved any data recently, check if thread should finish
-                        if PROFILER
-                            .thread
-                            .lock()
-                            .expect("WTF: Failed to acquire thread lock")
-                            .is_none()
-                        {
-                            break;
-                        }
-                    }
+macro_rules! arg_value_from_int {
+    ($($int:ty),*) => {
+        $(
+            impl From<$int> for ArgValue {
+                fn from(value: $int) -> Self {
+                    ArgValue::Int(value.into())
                 }
             }
+        )*
+    };
+}
 
-            file.flush().expect("WTF: Failed to write data to file");
-        })
-        .expect("WTF: Failed to spawn data writing thread");
-    let thread = Mutex::new(Some(thread));
-
-    Profiler { sender, thread }
-});
+arg_value_from_int!(i8, i16, i32, i64, u8, u16, u32);
 
-pub struct Profiler {
-    #[cfg(feature = "profile")]
-    sender: Sender<ProfilerMessage>,
-    #[cfg(feature = "profile")]
-    thread: Mutex<Option<JoinHandle<()>>>,
+macro_rules! arg_value_from_large_int {
+    ($($int:ty),*) => {
+        $(
+            /// Values too large for an `i64` are stored as `i64::MAX`.
+            impl From<$int> for ArgValue {
+                fn from(value: $int) -> Self {
+                    ArgValue::Int(i64::try_from(value).unwrap_or(i64::MAX))
+                }
+            }
+        )*
+    };
 }
 
-#[cfg(feature = "profile")]
-#[doc(hidden)]
-pub type ProfilingReturnType = TaskRecord;
-#[cfg(not(feature = "profile"))]
-#[doc(hidden)]
-pub type ProfilingReturnType = ();
+arg_value_from_large_int!(u64, usize, isize);
 
-impl Profiler {
-    #[must_use = "Must assign to a variable: \"_profile = Profiler::new_frame()\""]
-    pub fn new_frame() -> ProfilingReturnType {
-        #[cfg(feature = "profile")]
-        TaskRecord {
-            start: Instant::now(),
-        }
-    }
-
-    #[must_use = "Must assign to a variable: \"_profile = Profiler::profile_task()\""]
-    #[allow(unused_variables)]
-    pub fn profile_task(name: &'static str) -> ProfilingReturnType {
-        #[cfg(feature = "profile")]
-        {
-            PROFILER
-                .sender
-                .send(ProfilerMessage::TaskStart { name })
-                .expect("WTF: Failed to send task across a thread");
-            TaskRecord {
-                start: Instant::now(),
-            }
-        }
+impl From<f32> for ArgValue {
+    fn from(value: f32) -> Self {
+        ArgValue::Float(value.into())
     }
+}
 
-    pub fn end_profiling() {
-        #[cfg(feature = "profile")]
-        {
-            // Take the thread handle out, indicating to the data writing thread to stop
-            let mut profiler_lock = PROFILER
-                .thread
-                .lock()
-                .expect("WTF: Failed to acquire thread lock");
-            let thread = profiler_lock
-                .take()
-                .expect("WTF: Profiler::end_profiling() has already been called once");
-            // Drop the lock so the data writing thread can acquire it
-            drop(profiler_lock);
-            // Wait for the data writing thread to finish
-            thread
-                .join()
-                .expect("WTF: Failed to join data writing thread");
-        }
+impl From<f64> for ArgValue {
+    fn from(value: f64) -> Self {
+        ArgValue::Float(value)
     }
 }
 
-pub type ProfileData = Box<[TaskData]>;
-
-pub fn read_profile_data<R: Read>(reader: R) -> Result<ProfileData, bincode::Error> {
-    let mut reader = FrameDecoder::new(reader);
-    let mut frames = Vec::new();
-    // Keep trying to read TaskData's until there aren't any more to read
-    loop {
-        let frame = bincode::deserialize_from(&mut reader);
-        let frame = match frame.map_err(|err| *err) {
-            Ok(task) => task,
-            Err(bincode::ErrorKind::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => break,
-            Err(err) => return Err(Box::new(err)),
-        };
-        frames.push(frame);
+impl From<bool> for ArgValue {
+    fn from(value: bool) -> Self {
+        ArgValue::Bool(value)
     }
-    Ok(frames.into_boxed_slice())
 }
 
-#[derive(Clone, Deserialize)]
-pub struct TaskData {
-    pub name: Box<str>,
-    pub duration: Duration,
-    pub subtasks: Box<[Self]>,
+impl From<&str> for ArgValue {
+    fn from(value: &str) -> Self {
+        ArgValue::String(value.into())
+    }
 }
 
-#[cfg(feature = "profile")]
-enum ProfilerMessage {
-    TaskStart { name: &'static str },
-    TaskEnd { elapsed: Duration },
+impl From<String> for ArgValue {
+    fn from(value: String) -> Self {
+        ArgValue::String(value.into_boxed_str())
+    }
 }
 
-#[cfg(feature = "profile")]
-pub struct TaskRecord {
-    start: Instant,
+impl From<Box<str>> for ArgValue {
+    fn from(value: Box<str>) -> Self {
+        ArgValue::String(value)
+    }
 }
 
-#[cfg(feature = "profile")]
-impl Drop for TaskRecord {
-    fn drop(&mut self) {
-        let msg = ProfilerMessage::TaskEnd {
-            elapsed: self.start.elapsed(),
-        };
-        PROFILER
-            .sender
-            .send(msg)
-            .expect("WTF: Failed to send task across a thread");
-    }
+/// A moment in time recorded with [`Profiler::mark`], such as a level finishing loading
+#[derive(Clone)]
+pub struct Mark {
+    pub name: Box<str>,
+    /// When the mark was recorded, relative to the start of the capture
+    pub time: Duration,
+    /// An ID unique to the thread the mark was recorded on, like [`TaskData::thread`]
+    pub thread: u64,
+    /// Extra information recorded with [`Profiler::mark_with_payload`]
+    pub payload: Option<Box<str>>,
 }
@@ -0,0 +1,1745 @@
+use crate::{ArgValue, ProfilerConfig};
+use std::io;
+
+#[cfg(feature = "profile")]
+use crate::{Mark, TaskData};
+
+#[cfg(feature = "profile")]
+use crate::{KeepRecent, OverflowPolicy};
+
+#[cfg(feature = "profile")]
+use {
+    crate::stream::Listener,
+    crate::write::WriteTask,
+    crate::{Connection, ProfileWriter, ServeAddress},
+    chrono::offset::Utc,
+    flume::{Receiver, RecvTimeoutError, Sender, TrySendError},
+    once_cell::sync::OnceCell,
+    std::collections::{HashMap, HashSet, VecDeque},
+    std::env,
+    std::fs::{self, File},
+    std::mem,
+    std::panic,
+    std::process,
+    std::rc::Rc,
+    std::sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak},
+    std::thread::{self, JoinHandle},
+    std::time::{Duration, Instant},
+};
+
+#[cfg(feature = "profile")]
+static PROFILER: OnceCell<Profiler> = OnceCell::new();
+
+/// Get the profiler, starting profiling with the configuration from the environment if it hasn't started yet
+#[cfg(feature = "profile")]
+fn profiler() -> &'static Profiler {
+    if let Some(profiler) = PROFILER.get() {
+        return profiler;
+    }
+
+    let mut started = false;
+    let profiler = PROFILER.get_or_init(|| {
+        started = true;
+        Profiler::new(ProfilerConfig::from_env())
+    });
+    if started && profiler.config.capture_on_start {
+        if let Err(err) = profiler.start_capture_inner() {
+            eprintln!(
+                "WTF: Failed to start capture, no profile will be saved: {}",
+                err
+            );
+        }
+    }
+    profiler
+}
+
+#[cfg(feature = "profile")]
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The number of tasks dropped so far because the profiler reached its memory limit
+#[cfg(feature = "profile")]
+static DROPPED_TASKS: AtomicU64 = AtomicU64::new(0);
+
+/// The number of times recent frames have been dumped, used to name their files
+#[cfg(feature = "profile")]
+static DUMPS: AtomicU64 = AtomicU64::new(0);
+
+/// The number of events a thread buffers before sending them to the data writing thread
+#[cfg(feature = "profile")]
+const THREAD_EVENTS_CAPACITY: usize = 1024;
+
+/// The number of frames waiting to be sent to a viewer before it's disconnected for falling behind, about a second at 60fps
+#[cfg(feature = "profile")]
+const VIEWER_QUEUE_FRAMES: usize = 60;
+
+/// The longest the server waits before accepting viewers again after failing to, such as after running out of file descriptors
+#[cfg(feature = "profile")]
+const MAX_ACCEPT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Every thread's buffered events, so that they can all be sent when a frame ends. Threads that have exited are removed when
+/// the buffers are next sent
+///
+/// Each buffer is behind a mutex so that the thread ending a frame can send the other threads' tasks with it, even if those
+/// threads record nothing else. That's the only time a buffer is locked by another thread, once per frame, so recording an
+/// event almost always takes an uncontended lock, a couple of atomic operations. See the crate docs for the overhead this adds up to
+#[cfg(feature = "profile")]
+static THREAD_BUFFERS: Mutex<Vec<Weak<Mutex<ThreadEvents>>>> = Mutex::new(Vec::new());
+
+#[cfg(feature = "profile")]
+thread_local! {
+    static THREAD_EVENTS: Arc<Mutex<ThreadEvents>> = {
+        let thread_events = Arc::new(Mutex::new(ThreadEvents {
+            thread: NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed),
+            events: Vec::with_capacity(THREAD_EVENTS_CAPACITY),
+        }));
+        lock(&THREAD_BUFFERS).push(Arc::downgrade(&thread_events));
+        thread_events
+    };
+}
+
+/// Lock a mutex, even if a thread panicked while holding it, for code that must not panic
+#[cfg(feature = "profile")]
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Events recorded on a thread, waiting to be sent to the data writing thread in one batch
+#[cfg(feature = "profile")]
+struct ThreadEvents {
+    thread: u64,
+    events: Vec<Event>,
+}
+
+#[cfg(feature = "profile")]
+impl ThreadEvents {
+    /// Send the buffered events, returning false if the queue is full and they're still buffered
+    ///
+    /// When the queue is full, this only waits for room if `wait` is true or the overflow policy is [`OverflowPolicy::Block`]
+    fn send(&mut self, profiler: &Profiler, wait: bool) -> bool {
+        if self.events.is_empty() {
+            return true;
+        }
+
+        let wait = wait || profiler.config.overflow_policy == OverflowPolicy::Block;
+        if !wait && profiler.sender.is_full() {
+            return false;
+        }
+        let msg = ProfilerMessage::Events {
+            thread: self.thread,
+            events: mem::replace(&mut self.events, Vec::with_capacity(THREAD_EVENTS_CAPACITY)),
+        };
+        // If profiling has already ended, e.g. by the panic hook, the events are dropped
+        if wait {
+            let _ = profiler.sender.send(msg);
+        } else if let Err(TrySendError::Full(ProfilerMessage::Events { events, .. })) =
+            profiler.sender.try_send(msg)
+        {
+            self.events = events;
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(feature = "profile")]
+impl Drop for ThreadEvents {
+    fn drop(&mut self) {
+        // Send any remaining events when the thread exits
+        if let Some(profiler) = PROFILER.get() {
+            self.send(profiler, true);
+        }
+    }
+}
+
+/// Buffer an event on the current thread, returning false if it was dropped
+///
+/// The buffer is sent to the data writing thread when it's full, and every thread's buffer is sent when a frame ends.
+/// This must not panic, as it's called while dropping records, which may happen while unwinding from a panic
+#[cfg(feature = "profile")]
+fn record_event(profiler: &Profiler, event: Event) -> bool {
+    // If the thread is exiting and the buffer has already been dropped, the event is dropped
+    THREAD_EVENTS
+        .try_with(|thread_events| {
+            let mut thread_events = lock(thread_events);
+
+            // If the queue is still full, only new tasks are dropped, so that every recorded task still ends, and no frames are lost
+            if thread_events.events.len() >= THREAD_EVENTS_CAPACITY
+                && matches!(event, Event::TaskStart { .. })
+                && !thread_events.send(profiler, false)
+            {
+                DROPPED_TASKS.fetch_add(1, Ordering::Relaxed);
+                profiler.frame_dropped_tasks.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+
+            thread_events.events.push(event);
+            if thread_events.events.len() >= THREAD_EVENTS_CAPACITY {
+                thread_events.send(profiler, false);
+            }
+            true
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(feature = "profile")]
+fn record_mark(name: &'static str, payload: Option<impl Into<String>>) {
+    let time = Instant::now();
+    let profiler = profiler();
+    let capture = profiler.capture.load(Ordering::Acquire);
+    if capture != 0 {
+        let event = Event::Mark {
+            name,
+            payload: payload.map(Into::into),
+            time,
+            capture,
+        };
+        record_event(profiler, event);
+    }
+}
+
+/// Send the current thread's buffered events to the data writing thread
+#[cfg(feature = "profile")]
+fn send_thread_events(profiler: &Profiler, wait: bool) {
+    let _ = THREAD_EVENTS.try_with(|thread_events| lock(thread_events).send(profiler, wait));
+}
+
+/// Send every thread's buffered events to the data writing thread, including threads that are waiting and not recording anything
+#[cfg(feature = "profile")]
+fn send_all_thread_events(profiler: &Profiler, wait: bool) {
+    lock(&THREAD_BUFFERS).retain(|thread_events| match thread_events.upgrade() {
+        Some(thread_events) => {
+            lock(&thread_events).send(profiler, wait);
+            true
+        }
+        None => false,
+    });
+}
+
+pub struct Profiler {
+    #[cfg(feature = "profile")]
+    sender: Sender<ProfilerMessage>,
+    #[cfg(feature = "profile")]
+    thread: Mutex<Option<JoinHandle<()>>>,
+    #[cfg(feature = "profile")]
+    config: ProfilerConfig,
+    /// The number of the current capture, or 0 if not capturing
+    #[cfg(feature = "profile")]
+    capture: AtomicU64,
+    /// The number of captures started so far, locked while starting or stopping a capture
+    #[cfg(feature = "profile")]
+    capture_count: Mutex<u64>,
+    /// The number of tasks dropped since the last frame ended
+    #[cfg(feature = "profile")]
+    frame_dropped_tasks: AtomicU64,
+    /// Metadata set with [`Profiler::set_metadata`], written to each capture. Only changed while holding the capture lock
+    #[cfg(feature = "profile")]
+    metadata: Mutex<Vec<(String, String)>>,
+    /// Whether profiling was ended by the hook from [`Profiler::install_panic_hook`]
+    #[cfg(feature = "profile")]
+    ended_by_panic: AtomicBool,
+    /// The thread accepting viewers, if serving frames with [`ProfilerConfig::serve`]
+    #[cfg(feature = "profile")]
+    server: Mutex<Option<Server>>,
+}
+
+#[doc(hidden)]
+pub type ProfilingReturnType = TaskRecord;
+
+#[cfg(feature = "profile")]
+#[doc(hidden)]
+pub type FrameProfilingReturnType = FrameRecord;
+#[cfg(not(feature = "profile"))]
+#[doc(hidden)]
+pub type FrameProfilingReturnType = ();
+
+impl Profiler {
+    /// Start profiling with the given configuration.
+    ///
+    /// This must be called before any other profiler function. If it isn't, profiling starts the first time one is called,
+    /// using [`ProfilerConfig::from_env`].
+    ///
+    /// Returns an error if profiling has already started, or if [`ProfilerConfig::capture_on_start`] is set and the
+    /// profile's file couldn't be created.
+    #[allow(unused_variables)]
+    pub fn configure(config: ProfilerConfig) -> io::Result<()> {
+        #[cfg(feature = "profile")]
+        {
+            let capture_on_start = config.capture_on_start;
+            PROFILER
+                .set(Profiler::new(config))
+                .map_err(|_| io::Error::other("WTF: Profiling has already started"))?;
+            if capture_on_start {
+                profiler().start_capture_inner()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Start a new frame, which lasts until the returned record is dropped.
+    ///
+    /// Tasks from any thread that finish while no frame is in progress are added to the next frame, or to a final frame when the
+    /// capture stops.
+    /// Frames should not overlap; if a frame is started while another is in progress, it's treated as part of the outer frame.
+    ///
+    /// Each thread buffers its tasks, and every thread's buffer is sent when a frame ends, so tasks are added to the frame they
+    /// finished in, even if their thread records nothing afterwards.
+    #[must_use = "Must assign to a variable: \"_profile = Profiler::new_frame()\""]
+    pub fn new_frame() -> FrameProfilingReturnType {
+        #[cfg(feature = "profile")]
+        {
+            let profiler = profiler();
+            let capture = profiler.capture.load(Ordering::Acquire);
+            if capture == 0 {
+                return FrameRecord { active: None };
+            }
+
+            record_event(profiler, Event::FrameStart { capture });
+            FrameRecord {
+                active: Some((capture, Instant::now())),
+            }
+        }
+    }
+
+    #[must_use = "Must assign to a variable: \"_profile = Profiler::profile_task()\""]
+    #[allow(unused_variables)]
+    pub fn profile_task(name: &'static str) -> ProfilingReturnType {
+        #[cfg(feature = "profile")]
+        return TaskRecord::start(TaskName::Static(name));
+        #[cfg(not(feature = "profile"))]
+        TaskRecord {}
+    }
+
+    /// Like [`Profiler::profile_task`], but for names built at runtime, such as `format!("load_level {}", level_name)`.
+    ///
+    /// Repeated names are only stored once while writing the profile, but building the name still has a cost, even when not capturing.
+    /// Prefer [`Profiler::profile_task`] when the name is known at compile time.
+    #[must_use = "Must assign to a variable: \"_profile = Profiler::profile_task_dyn()\""]
+    #[allow(unused_variables)]
+    pub fn profile_task_dyn(name: impl Into<String>) -> ProfilingReturnType {
+        #[cfg(feature = "profile")]
+        return TaskRecord::start(TaskName::Dynamic(name.into()));
+        #[cfg(not(feature = "profile"))]
+        TaskRecord {}
+    }
+
+    /// Record the value of a counter, such as the number of draw calls or entities, for the current frame.
+    ///
+    /// Counters are plotted alongside frame times in the GUI. Only the last value recorded during a frame is kept.
+    /// Like tasks, values recorded while no frame is in progress belong to the next frame.
+    #[allow(unused_variables)]
+    pub fn counter(name: &'static str, value: f64) {
+        #[cfg(feature = "profile")]
+        {
+            let profiler = profiler();
+            let capture = profiler.capture.load(Ordering::Acquire);
+            if capture != 0 {
+                record_event(
+                    profiler,
+                    Event::Counter {
+                        name,
+                        value,
+                        capture,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Record a moment in time during the current frame, such as a level finishing loading or input being received.
+    ///
+    /// Marks are shown on the frame timeline and in the frame's task tree, so that slow frames can be matched up with what
+    /// was happening in your game. Like tasks, marks recorded while no frame is in progress belong to the next frame.
+    #[allow(unused_variables)]
+    pub fn mark(name: &'static str) {
+        #[cfg(feature = "profile")]
+        record_mark(name, None::<String>);
+    }
+
+    /// Like [`Profiler::mark`], but with a string of extra information, such as the name of the level that was loaded.
+    ///
+    /// The payload is only converted to a `String` while capturing.
+    #[allow(unused_variables)]
+    pub fn mark_with_payload(name: &'static str, payload: impl Into<String>) {
+        #[cfg(feature = "profile")]
+        record_mark(name, Some(payload));
+    }
+
+    /// Save the frames kept in memory by [`ProfilerConfig::keep_recent`] to a new profile file, such as when a player reports a hitch.
+    ///
+    /// The frames are written by the data writing thread, so the file may not be complete when this returns.
+    /// Returns an error if not capturing, if [`ProfilerConfig::keep_recent`] isn't set, or if the file couldn't be created.
+    pub fn dump_recent() -> io::Result<()> {
+        #[cfg(feature = "profile")]
+        profiler().dump_recent_inner()?;
+        Ok(())
+    }
+
+    /// Start recording to a new profile file.
+    ///
+    /// Returns an error if already capturing, if profiling has ended, or if the file couldn't be created.
+    pub fn start_capture() -> io::Result<()> {
+        #[cfg(feature = "profile")]
+        profiler().start_capture_inner()?;
+        Ok(())
+    }
+
+    /// Stop recording, and finish writing the current capture's file. Does nothing if not capturing.
+    pub fn stop_capture() {
+        #[cfg(feature = "profile")]
+        {
+            let profiler = profiler();
+            let _capture_count = profiler
+                .capture_count
+                .lock()
+                .expect("WTF: Failed to acquire capture lock");
+            if profiler.capture.swap(0, Ordering::AcqRel) != 0 {
+                // Every thread's tasks are written before the capture stops, even those that finished after the last frame
+                send_all_thread_events(profiler, true);
+                let _ = profiler.sender.send(ProfilerMessage::StopCapture);
+            }
+        }
+    }
+
+    /// Stop capturing if currently capturing, and otherwise start capturing. Returns whether now capturing.
+    ///
+    /// Useful for binding to a hotkey.
+    pub fn toggle_capture() -> io::Result<bool> {
+        if Self::is_capturing() {
+            Self::stop_capture();
+            Ok(false)
+        } else {
+            Self::start_capture()?;
+            Ok(true)
+        }
+    }
+
+    /// Record a key/value pair describing this run of your game, such as its version, the level being played, or graphics settings.
+    /// Setting a key again replaces its value.
+    ///
+    /// The metadata is saved to the current capture if there is one, and to every capture started afterwards.
+    /// Profiles also record the program name, start time, OS, CPU model, and number of CPU cores automatically.
+    /// It can be read back with [`Profile::metadata`](crate::Profile::metadata).
+    #[allow(unused_variables)]
+    pub fn set_metadata(key: impl Into<String>, value: impl Into<String>) {
+        #[cfg(feature = "profile")]
+        {
+            let profiler = profiler();
+            let (key, value) = (key.into(), value.into());
+            let _capture_count = profiler
+                .capture_count
+                .lock()
+                .expect("WTF: Failed to acquire capture lock");
+            crate::format::set_metadata(
+                &mut profiler
+                    .metadata
+                    .lock()
+                    .expect("WTF: Failed to acquire metadata lock"),
+                key.clone(),
+                value.clone(),
+            );
+            if profiler.capture.load(Ordering::Acquire) != 0 {
+                let _ = profiler
+                    .sender
+                    .send(ProfilerMessage::Metadata { key, value });
+            }
+        }
+    }
+
+    /// The total number of tasks dropped because the profiler reached its memory limit.
+    ///
+    /// See [`ProfilerConfig::max_buffered_events`].
+    pub fn dropped_tasks() -> u64 {
+        #[cfg(feature = "profile")]
+        return DROPPED_TASKS.load(Ordering::Relaxed);
+        #[cfg(not(feature = "profile"))]
+        0
+    }
+
+    pub fn is_capturing() -> bool {
+        #[cfg(feature = "profile")]
+        return profiler().capture.load(Ordering::Acquire) != 0;
+        #[cfg(not(feature = "profile"))]
+        false
+    }
+
+    /// Stop capturing, and wait for everything recorded to be written. Call once, at the end of your game.
+    ///
+    /// Does nothing if profiling has already been ended by the hook from [`Profiler::install_panic_hook`].
+    pub fn end_profiling() {
+        #[cfg(feature = "profile")]
+        {
+            let profiler = match PROFILER.get() {
+                Some(profiler) => profiler,
+                // Profiling never started, so there's nothing to end
+                None => return,
+            };
+            // Take the thread handle out, so that profiling can only be ended once. The lock must be released before
+            // panicking, as the panic hook takes it too
+            let thread = profiler
+                .thread
+                .lock()
+                .expect("WTF: Failed to acquire thread lock")
+                .take();
+            let thread = match thread {
+                Some(thread) => thread,
+                None if profiler.ended_by_panic.load(Ordering::Acquire) => return,
+                None => panic!("WTF: Profiler::end_profiling() has already been called once"),
+            };
+            // Tell the data writing thread to stop once it's written everything sent before this, and wait for it to finish
+            Self::stop_capture();
+            profiler
+                .sender
+                .send(ProfilerMessage::EndProfiling)
+                .expect("WTF: Failed to send task across a thread");
+            thread
+                .join()
+                .expect("WTF: Failed to join data writing thread");
+            profiler.stop_serving();
+        }
+    }
+
+    /// Install a panic hook that ends profiling when the main thread panics, so that the profile is saved.
+    ///
+    /// Panics on other threads are ignored, as a program may recover from them, such as a worker pool restarting a worker.
+    /// Panics on the main thread end profiling even if they're caught with [`std::panic::catch_unwind`].
+    ///
+    /// The previously installed panic hook is still run. Tasks still in progress when the panic occurs are not saved.
+    /// If profiling has already ended, the hook does nothing.
+    pub fn install_panic_hook() {
+        #[cfg(feature = "profile")]
+        {
+            let previous_hook = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                previous_hook(info);
+                if thread::current().name() != Some("main") {
+                    return;
+                }
+
+                let profiler = match PROFILER.get() {
+                    Some(profiler) => profiler,
+                    None => return,
+                };
+                // Must not panic here, as panicking inside a panic hook aborts the program
+                let thread = match profiler.thread.lock() {
+                    Ok(mut thread) => thread.take(),
+                    Err(_) => return,
+                };
+                if let Some(thread) = thread {
+                    profiler.ended_by_panic.store(true, Ordering::Release);
+                    profiler.capture.store(0, Ordering::Release);
+                    if profiler.sender.send(ProfilerMessage::EndProfiling).is_ok() {
+                        let _ = thread.join();
+                    }
+                    profiler.stop_serving();
+                }
+            }));
+        }
+    }
+}
+
+#[cfg(feature = "profile")]
+impl Profiler {
+    /// Spawn a thread to write captures to files
+    fn new(config: ProfilerConfig) -> Self {
+        let (sender, reciever) = match config.max_buffered_events {
+            Some(max_buffered_events) => {
+                flume::bounded(max_buffered_events.div_ceil(THREAD_EVENTS_CAPACITY).max(1))
+            }
+            None => flume::unbounded(),
+        };
+
+        let thread = thread::Builder::new()
+            .name("wtf-profiler".to_string())
+            .spawn({
+                let config = config.clone();
+                move || write_captures(reciever, config)
+            })
+            .expect("WTF: Failed to spawn data writing thread");
+        let thread = Mutex::new(Some(thread));
+
+        let mut server = None;
+        if let Some(address) = &config.serve {
+            match serve(address, sender.clone()) {
+                Ok(started) => server = Some(started),
+                Err(err) => eprintln!(
+                    "WTF: Failed to serve frames on {}, they will only be saved to files: {}",
+                    address, err
+                ),
+            }
+        }
+
+        Self {
+            sender,
+            thread,
+            config,
+            capture: AtomicU64::new(0),
+            capture_count: Mutex::new(0),
+            frame_dropped_tasks: AtomicU64::new(0),
+            ended_by_panic: AtomicBool::new(false),
+            metadata: Mutex::new(Vec::new()),
+            server: Mutex::new(server),
+        }
+    }
+
+    /// Stop accepting viewers, and remove the Unix socket if serving on one. Must not panic, as it's called from the panic hook
+    fn stop_serving(&self) {
+        let server = match lock(&self.server).take() {
+            Some(server) => server,
+            None => return,
+        };
+        server.stopping.store(true, Ordering::Release);
+        // Wake the thread from waiting for a viewer. If it can't be reached, it's left waiting until the program exits
+        if Connection::connect(&server.address).is_ok() || server.thread.is_finished() {
+            let _ = server.thread.join();
+        }
+        if let Err(err) = server.address.remove_socket() {
+            eprintln!("WTF: Failed to remove {}: {}", server.address, err);
+        }
+    }
+
+    fn start_capture_inner(&self) -> io::Result<()> {
+        let mut capture_count = self
+            .capture_count
+            .lock()
+            .expect("WTF: Failed to acquire capture lock");
+        if self.capture.load(Ordering::Acquire) != 0 {
+            return Err(io::Error::other("WTF: Already capturing"));
+        }
+        if self
+            .thread
+            .lock()
+            .expect("WTF: Failed to acquire thread lock")
+            .is_none()
+        {
+            return Err(io::Error::other("WTF: Profiling has already ended"));
+        }
+
+        let capture = *capture_count + 1;
+        let metadata = capture_metadata(
+            &self.config,
+            self.metadata
+                .lock()
+                .expect("WTF: Failed to acquire metadata lock")
+                .clone(),
+        );
+        // Captures that only keep recent frames have no file of their own, only one for each dump
+        let file = match self.config.keep_recent {
+            Some(_) => None,
+            None => {
+                let file = create_file(&self.config, capture, None)?;
+                Some(Box::new(ProfileWriter::with_metadata(
+                    file,
+                    metadata.clone(),
+                )?))
+            }
+        };
+        *capture_count = capture;
+
+        // The capture must be started on the data writing thread before any of its tasks are sent
+        self.sender
+            .send(ProfilerMessage::StartCapture {
+                file,
+                metadata,
+                capture,
+                start: Instant::now(),
+            })
+            .map_err(|_| io::Error::other("WTF: Profiling has already ended"))?;
+        self.capture.store(capture, Ordering::Release);
+        Ok(())
+    }
+
+    fn dump_recent_inner(&self) -> io::Result<()> {
+        let _capture_count = self
+            .capture_count
+            .lock()
+            .expect("WTF: Failed to acquire capture lock");
+        let capture = self.capture.load(Ordering::Acquire);
+        if capture == 0 {
+            return Err(io::Error::other("WTF: Not capturing"));
+        }
+        if self.config.keep_recent.is_none() {
+            return Err(io::Error::other(
+                "WTF: Recent frames aren't kept, see ProfilerConfig::keep_recent",
+            ));
+        }
+
+        let dump = DUMPS.fetch_add(1, Ordering::Relaxed) + 1;
+        let file = create_file(&self.config, capture, Some(dump))?;
+        // Include the frames that have finished but not been sent yet
+        send_all_thread_events(self, true);
+        self.sender
+            .send(ProfilerMessage::DumpRecent { file, capture })
+            .map_err(|_| io::Error::other("WTF: Profiling has already ended"))
+    }
+}
+
+/// The thread accepting viewers, see [`serve`]
+#[cfg(feature = "profile")]
+struct Server {
+    thread: JoinHandle<()>,
+    /// Where to connect to wake the thread, and the Unix socket to remove once it's stopped
+    address: ServeAddress,
+    stopping: Arc<AtomicBool>,
+}
+
+/// Spawn a thread to accept viewers, and pass them to the data writing thread
+#[cfg(feature = "profile")]
+fn serve(address: &ServeAddress, sender: Sender<ProfilerMessage>) -> io::Result<Server> {
+    let listener = Listener::bind(address)?;
+    let address = listener.local_address()?;
+    let stopping = Arc::new(AtomicBool::new(false));
+    let thread = thread::Builder::new()
+        .name("wtf-server".to_string())
+        .spawn({
+            let stopping = Arc::clone(&stopping);
+            move || {
+                let mut retry_delay = None;
+                while !stopping.load(Ordering::Acquire) {
+                    match listener.accept() {
+                        // Woken by Profiler::stop_serving()
+                        _ if stopping.load(Ordering::Acquire) => break,
+                        Ok(connection) => {
+                            retry_delay = None;
+                            // Profiling has ended, so there's nothing left to serve
+                            if sender.send(ProfilerMessage::Connect(connection)).is_err() {
+                                break;
+                            }
+                        }
+                        // Errors such as running out of file descriptors last a while, so wait longer after each one in a
+                        // row, and only report the first
+                        Err(err) => {
+                            let delay = match retry_delay {
+                                Some(delay) => MAX_ACCEPT_RETRY_DELAY.min(delay * 2),
+                                None => {
+                                    eprintln!("WTF: Failed to accept viewer: {}", err);
+                                    Duration::from_millis(10)
+                                }
+                            };
+                            retry_delay = Some(delay);
+                            thread::sleep(delay);
+                        }
+                    }
+                }
+            }
+        })?;
+    Ok(Server {
+        thread,
+        address,
+        stopping,
+    })
+}
+
+/// Create the file for a capture, or for one of its dumps of recent frames, named using the file name template
+#[cfg(feature = "profile")]
+fn create_file(config: &ProfilerConfig, capture: u64, dump: Option<u64>) -> io::Result<File> {
+    // Dumps are numbered even if the template doesn't include their number, so that they don't overwrite each other
+    let mut file_name = config.file_name.clone();
+    if dump.is_some() && !file_name.contains("{dump}") {
+        let extension = file_name.rfind('.').unwrap_or(file_name.len());
+        file_name.insert_str(extension, "-{dump}");
+    }
+    let file_name = file_name
+        .replace("{exe}", &program_name())
+        .replace(
+            "{timestamp}",
+            &Utc::now().format("%Y-%m-%d-%H-%M-%S").to_string(),
+        )
+        .replace("{capture}", &capture.to_string())
+        .replace("{label}", config.label.as_deref().unwrap_or(""))
+        .replace("{pid}", &process::id().to_string())
+        .replace(
+            "{dump}",
+            &dump.map_or(String::new(), |dump| dump.to_string()),
+        );
+    let path = config.output_dir.join(file_name);
+
+    fs::create_dir_all(&config.output_dir)
+        .and_then(|_| File::create(&path))
+        .map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("Failed to create {}: {}", path.display(), err),
+            )
+        })
+}
+
+/// The metadata written to the header of a capture's files, starting now: what's detected automatically,
+/// then the metadata set with [`Profiler::set_metadata`]
+#[cfg(feature = "profile")]
+fn capture_metadata(
+    config: &ProfilerConfig,
+    user_metadata: Vec<(String, String)>,
+) -> Vec<(String, String)> {
+    let mut metadata = vec![
+        ("program".to_string(), program_name()),
+        ("start_time".to_string(), Utc::now().to_rfc3339()),
+        (
+            "wtf_version".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+        ),
+        ("os".to_string(), env::consts::OS.to_string()),
+        ("arch".to_string(), env::consts::ARCH.to_string()),
+    ];
+    if let Some(cpu) = cpu_model() {
+        metadata.push(("cpu".to_string(), cpu));
+    }
+    if let Ok(cores) = thread::available_parallelism() {
+        metadata.push(("cpu_cores".to_string(), cores.to_string()));
+    }
+    if let Some(label) = &config.label {
+        metadata.push(("label".to_string(), label.clone()));
+    }
+    for (key, value) in user_metadata {
+        crate::format::set_metadata(&mut metadata, key, value);
+    }
+    metadata
+}
+
+/// The file name of the program being profiled
+#[cfg(feature = "profile")]
+fn program_name() -> String {
+    env::current_exe()
+        .ok()
+        .and_then(|path| Some(path.file_name()?.to_str()?.to_string()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// The name of the CPU, if the OS provides it
+#[cfg(feature = "profile")]
+fn cpu_model() -> Option<String> {
+    #[cfg(target_os = "linux")]
+    let model = fs::read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|cpuinfo| {
+            cpuinfo.lines().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                (key.trim() == "model name").then(|| value.trim().to_string())
+            })
+        });
+    #[cfg(target_os = "macos")]
+    let model = process::Command::new("sysctl")
+        .args(["-n", "machdep.cpu.brand_string"])
+        .output()
+        .ok()
+        .and_then(|output| Some(String::from_utf8(output.stdout).ok()?.trim().to_string()));
+    #[cfg(target_os = "windows")]
+    let model = env::var("PROCESSOR_IDENTIFIER").ok();
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    let model = None;
+
+    model.filter(|model| !model.is_empty())
+}
+
+#[cfg(feature = "profile")]
+enum TaskName {
+    Static(&'static str),
+    Dynamic(String),
+}
+
+/// A task name on the data writing thread. Dynamic names are interned, so repeated names share one allocation
+#[cfg(feature = "profile")]
+enum TaskNameS {
+    Static(&'static str),
+    Interned(Rc<str>),
+}
+
+#[cfg(feature = "profile")]
+struct TaskDataS {
+    name: TaskNameS,
+    start: Option<Duration>,
+    duration: Duration,
+    thread: u64,
+    args: Vec<(&'static str, ArgValue)>,
+    subtasks: Vec<Self>,
+}
+
+#[cfg(feature = "profile")]
+impl TaskDataS {
+    /// Copy the task and its subtasks, such as to send them to another thread
+    fn to_task_data(&self) -> TaskData {
+        TaskData {
+            name: self.name().into(),
+            start: self.start,
+            duration: self.duration,
+            thread: self.thread,
+            args: self
+                .args
+                .iter()
+                .map(|(key, value)| ((*key).into(), value.clone()))
+                .collect(),
+            subtasks: self.subtasks.iter().map(Self::to_task_data).collect(),
+            ..TaskData::default()
+        }
+    }
+}
+
+#[cfg(feature = "profile")]
+impl WriteTask for TaskDataS {
+    type ArgName = &'static str;
+
+    fn name(&self) -> &str {
+        match &self.name {
+            TaskNameS::Static(name) => name,
+            TaskNameS::Interned(name) => name,
+        }
+    }
+
+    fn start(&self) -> Option<Duration> {
+        self.start
+    }
+
+    fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    fn thread(&self) -> u64 {
+        self.thread
+    }
+
+    fn args(&self) -> &[(&'static str, ArgValue)] {
+        &self.args
+    }
+
+    fn subtasks(&self) -> &[Self] {
+        &self.subtasks
+    }
+}
+
+/// The state of the capture currently being written
+#[cfg(feature = "profile")]
+struct Capture {
+    output: CaptureOutput,
+    /// The metadata of the capture so far, which is written to the header of new viewers' streams and dumps
+    metadata: Vec<(String, String)>,
+    /// Connections serving this capture's frames, see [`ProfilerConfig::serve`]
+    viewers: Vec<Viewer>,
+    capture: u64,
+    start: Instant,
+    frame_number: usize,
+    /// The number of frames in progress, which is more than 1 only if frames were incorrectly nested
+    frame_depth: usize,
+    /// Finished top level tasks from all threads, belonging to the frame in progress or the next frame
+    frame_subtasks: Vec<TaskDataS>,
+    /// The latest value of each counter recorded since the last frame ended
+    frame_counters: Vec<(&'static str, f64)>,
+    /// Marks recorded since the last frame ended
+    frame_marks: Vec<Mark>,
+    /// Each thread has its own stack of unfinished tasks, so that tasks from different threads don't nest inside each other
+    parent_stacks: HashMap<u64, ParentStack>,
+    interned_names: HashSet<Rc<str>>,
+    last_flush: Instant,
+    flush_interval: Duration,
+    overflow_policy: OverflowPolicy,
+    /// The maximum number of tasks to hold in memory before dropping new ones, or `None` if tasks are never dropped here
+    max_held_tasks: Option<usize>,
+    /// The number of tasks held in memory that haven't been written yet, only tracked if `max_held_tasks` is set
+    held_tasks: usize,
+    /// The number of tasks dropped here since the last frame ended
+    frame_dropped_tasks: u64,
+}
+
+/// Where a capture's frames are written, besides to viewers
+#[cfg(feature = "profile")]
+enum CaptureOutput {
+    File(Box<ProfileWriter<File>>),
+    /// Only the most recent frames are kept, see [`ProfilerConfig::keep_recent`]
+    Recent(Box<RecentFrames>),
+}
+
+/// A connection serving a capture's frames, written to on a thread of its own so that a slow viewer can't stall the data
+/// writing thread
+#[cfg(feature = "profile")]
+struct Viewer {
+    sender: Sender<ViewerMessage>,
+    thread: JoinHandle<()>,
+}
+
+#[cfg(feature = "profile")]
+enum ViewerMessage {
+    Frame(Arc<TaskData>),
+    Metadata { key: String, value: String },
+}
+
+#[cfg(feature = "profile")]
+impl Viewer {
+    fn spawn(connection: Connection, metadata: Vec<(String, String)>) -> io::Result<Self> {
+        let (sender, reciever) = flume::bounded(VIEWER_QUEUE_FRAMES);
+        let thread = thread::Builder::new()
+            .name("wtf-viewer".to_string())
+            .spawn(move || {
+                // If the viewer disconnects or stops accepting data, the thread ends, and the viewer is dropped the next time
+                // it's sent anything
+                let _ = write_viewer(connection, metadata, reciever);
+            })?;
+        Ok(Self { sender, thread })
+    }
+
+    /// Queue a message for the viewer. Returns false if the viewer has disconnected or fallen too far behind, and
+    /// should be dropped
+    fn send(&self, message: ViewerMessage) -> bool {
+        self.sender.try_send(message).is_ok()
+    }
+
+    /// End the viewer's stream once its queued frames are written, and return its thread
+    fn finish(self) -> JoinHandle<()> {
+        // Dropping the sender ends the thread's loop
+        drop(self.sender);
+        self.thread
+    }
+}
+
+/// Runs on a viewer's thread, writing the frames and metadata sent to it until the capture finishes
+#[cfg(feature = "profile")]
+fn write_viewer(
+    connection: Connection,
+    metadata: Vec<(String, String)>,
+    reciever: Receiver<ViewerMessage>,
+) -> io::Result<()> {
+    let mut writer = ProfileWriter::with_metadata(connection, metadata)?;
+    for message in reciever {
+        match message {
+            ViewerMessage::Frame(frame) => {
+                writer.write_frame(&frame)?;
+                writer.flush()?;
+            }
+            ViewerMessage::Metadata { key, value } => writer.set_metadata(key, value)?,
+        }
+    }
+    writer.finish().map(drop)
+}
+
+/// The most recent frames of a capture, kept in memory until they're dumped to a file
+#[cfg(feature = "profile")]
+struct RecentFrames {
+    frames: VecDeque<RecentFrame>,
+    keep: KeepRecent,
+    dump_slow_frames: Option<Duration>,
+    dump_frames_after: usize,
+    /// The automatic dump still waiting for the frames after a slow frame
+    open_dump: Option<OpenDump>,
+    /// The number of the last frame dumped automatically, so that automatic dumps don't overlap
+    last_dumped: usize,
+    /// Used to name the dumps' files
+    config: ProfilerConfig,
+}
+
+/// A frame kept by [`RecentFrames`], with everything needed to write it later
+#[cfg(feature = "profile")]
+struct RecentFrame {
+    number: usize,
+    frame: TaskDataS,
+    dropped_tasks: u64,
+    counters: Vec<(&'static str, f64)>,
+    marks: Vec<Mark>,
+}
+
+#[cfg(feature = "profile")]
+struct OpenDump {
+    writer: ProfileWriter<File>,
+    /// The number of frames still to be written after the last slow frame
+    remaining: usize,
+}
+
+#[cfg(feature = "profile")]
+impl RecentFrames {
+    fn new(config: &ProfilerConfig) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            keep: config
+                .keep_recent
+                .expect("WTF: Captures without a file must keep recent frames"),
+            dump_slow_frames: config.dump_slow_frames,
+            dump_frames_after: config.dump_frames_after,
+            open_dump: None,
+            last_dumped: 0,
+            config: config.clone(),
+        }
+    }
+
+    /// Add a frame, forgetting the oldest frames once there are too many, and dump the frames automatically if needed
+    fn push(&mut self, frame: RecentFrame, capture: u64, metadata: &[(String, String)]) {
+        let slow = self
+            .dump_slow_frames
+            .is_some_and(|slow| frame.frame.duration > slow);
+        let end = frame.frame.start.unwrap_or_default() + frame.frame.duration;
+        self.frames.push_back(frame);
+
+        while self.frames.len() > 1 {
+            let expired = match self.keep {
+                KeepRecent::Frames(count) => self.frames.len() > count,
+                KeepRecent::Time(time) => {
+                    end.saturating_sub(self.frames[0].frame.start.unwrap_or_default()) > time
+                }
+            };
+            if !expired {
+                break;
+            }
+            self.frames.pop_front();
+        }
+
+        if let Err(err) = self.dump_automatically(slow, capture, metadata) {
+            eprintln!(
+                "WTF: Failed to dump recent frames after a slow frame: {}",
+                err
+            );
+            self.open_dump = None;
+        }
+    }
+
+    /// Start a new dump if the newest frame is slow, or add it to the dump waiting for frames after a slow frame
+    fn dump_automatically(
+        &mut self,
+        slow: bool,
+        capture: u64,
+        metadata: &[(String, String)],
+    ) -> io::Result<()> {
+        let newest = self
+            .frames
+            .back()
+            .expect("WTF: Recent frames are empty after adding a frame");
+        match &mut self.open_dump {
+            Some(dump) => {
+                write_recent_frame(&mut dump.writer, newest)?;
+                // Another slow frame extends the dump, so that it has frames after both
+                dump.remaining = if slow {
+                    self.dump_frames_after
+                } else {
+                    dump.remaining.saturating_sub(1)
+                };
+            }
+            None if slow => {
+                // Tag the dump with the frame that caused it
+                let mut metadata = metadata.to_vec();
+                crate::format::set_metadata(
+                    &mut metadata,
+                    "slow_frame".to_string(),
+                    newest.frame.name().to_string(),
+                );
+                let dump = DUMPS.fetch_add(1, Ordering::Relaxed) + 1;
+                let file = create_file(&self.config, capture, Some(dump))?;
+                let mut writer = ProfileWriter::with_metadata(file, metadata)?;
+                // Frames already in the previous dump aren't dumped again
+                for recent in self
+                    .frames
+                    .iter()
+                    .filter(|recent| recent.number > self.last_dumped)
+                {
+                    write_recent_frame(&mut writer, recent)?;
+                }
+                self.open_dump = Some(OpenDump {
+                    writer,
+                    remaining: self.dump_frames_after,
+                });
+            }
+            None => return Ok(()),
+        }
+        self.last_dumped = newest.number;
+
+        if self
+            .open_dump
+            .as_ref()
+            .is_some_and(|dump| dump.remaining == 0)
+        {
+            self.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Write the frames to a new profile
+    fn dump(&self, file: File, metadata: Vec<(String, String)>) -> io::Result<()> {
+        let mut writer = ProfileWriter::with_metadata(file, metadata)?;
+        for recent in &self.frames {
+            write_recent_frame(&mut writer, recent)?;
+        }
+        writer.finish().map(drop)
+    }
+
+    /// Finish the automatic dump waiting for frames after a slow frame, if there is one
+    fn finish(&mut self) -> io::Result<()> {
+        match self.open_dump.take() {
+            Some(dump) => dump.writer.finish().map(drop),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "profile")]
+fn write_recent_frame(writer: &mut ProfileWriter<File>, recent: &RecentFrame) -> io::Result<()> {
+    writer.write_frame_record(
+        &recent.frame,
+        recent.dropped_tasks,
+        &recent.counters,
+        &recent.marks,
+    )
+}
+
+#[cfg(feature = "profile")]
+#[derive(Default)]
+struct ParentStack {
+    tasks: Vec<TaskDataS>,
+    /// The number of unfinished tasks that were dropped, which are always above the tasks in the stack
+    dropped: usize,
+}
+
+/// Runs on the data writing thread, assembling events into frames and writing them to the current capture's file
+#[cfg(feature = "profile")]
+fn write_captures(reciever: Receiver<ProfilerMessage>, config: ProfilerConfig) {
+    let mut current: Option<Capture> = None;
+    // Viewers that connected while not capturing, waiting for the next capture
+    let mut waiting_viewers = Vec::new();
+    // The threads of viewers of finished captures, still writing their last frames
+    let mut viewer_threads = Vec::new();
+
+    loop {
+        let msg = reciever.recv_timeout(Duration::from_millis(100));
+        match msg {
+            Ok(ProfilerMessage::StartCapture {
+                file,
+                metadata,
+                capture,
+                start,
+            }) => {
+                let mut capture = Capture {
+                    output: match file {
+                        Some(file) => CaptureOutput::File(file),
+                        None => CaptureOutput::Recent(Box::new(RecentFrames::new(&config))),
+                    },
+                    metadata,
+                    viewers: Vec::new(),
+                    capture,
+                    start,
+                    frame_number: 0,
+                    frame_depth: 0,
+                    frame_subtasks: Vec::new(),
+                    frame_counters: Vec::new(),
+                    frame_marks: Vec::new(),
+                    parent_stacks: HashMap::new(),
+                    interned_names: HashSet::new(),
+                    last_flush: Instant::now(),
+                    flush_interval: config.flush_interval,
+                    overflow_policy: config.overflow_policy,
+                    // Blocking only limits the queue, as the writing thread waiting for itself would never finish
+                    max_held_tasks: match config.overflow_policy {
+                        OverflowPolicy::Block => None,
+                        _ => config.max_buffered_events.map(|max| max / 2),
+                    },
+                    held_tasks: 0,
+                    frame_dropped_tasks: 0,
+                };
+                for connection in waiting_viewers.drain(..) {
+                    capture.add_viewer(connection);
+                }
+                current = Some(capture);
+            }
+            Ok(ProfilerMessage::Connect(connection)) => match &mut current {
+                Some(current) => current.add_viewer(connection),
+                None => waiting_viewers.push(connection),
+            },
+            Ok(ProfilerMessage::DumpRecent { file, capture }) => {
+                if let Some(current) = &current {
+                    if let (CaptureOutput::Recent(recent), true) =
+                        (&current.output, current.capture == capture)
+                    {
+                        if let Err(err) = recent.dump(file, current.metadata.clone()) {
+                            eprintln!("WTF: Failed to dump recent frames: {}", err);
+                        }
+                    }
+                }
+            }
+            Ok(ProfilerMessage::StopCapture) => {
+                if let Some(capture) = current.take() {
+                    viewer_threads.retain(|thread: &JoinHandle<()>| !thread.is_finished());
+                    capture
+                        .finish(&mut viewer_threads)
+                        .expect("WTF: Failed to write data to file");
+                }
+            }
+            Ok(ProfilerMessage::Metadata { key, value }) => {
+                if let Some(current) = &mut current {
+                    if let CaptureOutput::File(file) = &mut current.output {
+                        file.set_metadata(key.clone(), value.clone())
+                            .expect("WTF: Failed to write data to file");
+                    }
+                    current.viewers.retain(|viewer| {
+                        viewer.send(ViewerMessage::Metadata {
+                            key: key.clone(),
+                            value: value.clone(),
+                        })
+                    });
+                    crate::format::set_metadata(&mut current.metadata, key, value);
+                }
+            }
+            Ok(ProfilerMessage::Events { thread, events }) => {
+                if let Some(current) = &mut current {
+                    for event in events {
+                        current.record(thread, event);
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                // Haven't recieved any data recently, so flush any frames waiting on the flush interval
+                if let Some(current) = &mut current {
+                    current.flush().expect("WTF: Failed to write data to file");
+                }
+            }
+            Ok(ProfilerMessage::EndProfiling) | Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    if let Some(current) = current {
+        current
+            .finish(&mut viewer_threads)
+            .expect("WTF: Failed to write data to file");
+    }
+    // Viewers' threads stop waiting for them after a timeout, so this can't hang
+    for thread in viewer_threads {
+        let _ = thread.join();
+    }
+}
+
+#[cfg(feature = "profile")]
+impl Capture {
+    fn record(&mut self, thread: u64, event: Event) {
+        match event {
+            Event::TaskStart { name, capture } => {
+                // Ignore tasks from previous captures
+                if capture != self.capture {
+                    return;
+                }
+
+                let name = match name {
+                    TaskName::Static(name) => TaskNameS::Static(name),
+                    TaskName::Dynamic(name) => match self.interned_names.get(name.as_str()) {
+                        Some(interned) => TaskNameS::Interned(Rc::clone(interned)),
+                        None => {
+                            let interned = Rc::from(name);
+                            self.interned_names.insert(Rc::clone(&interned));
+                            TaskNameS::Interned(interned)
+                        }
+                    },
+                };
+
+                // Tasks started inside a dropped task are also dropped, as there's nothing to add them to
+                let full = self
+                    .max_held_tasks
+                    .is_some_and(|max| self.held_tasks >= max);
+                let parent_stack = self.parent_stacks.entry(thread).or_default();
+                if full || parent_stack.dropped > 0 {
+                    parent_stack.dropped += 1;
+                    self.frame_dropped_tasks += 1;
+                    DROPPED_TASKS.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                if self.max_held_tasks.is_some() {
+                    self.held_tasks += 1;
+                }
+
+                // Create a new task with a placeholder start and duration, and push it to the top of the thread's parent stack
+                let task = TaskDataS {
+                    name,
+                    start: None,
+                    duration: Duration::default(),
+                    thread,
+                    args: Vec::new(),
+                    subtasks: Vec::new(),
+                };
+                parent_stack.tasks.push(task);
+            }
+            Event::TaskArgs { args, capture } => {
+                if capture != self.capture {
+                    return;
+                }
+
+                // The args belong to the task at the top of the thread's parent stack, unless it was dropped
+                let parent_stack = self.parent_stacks.entry(thread).or_default();
+                if parent_stack.dropped > 0 {
+                    return;
+                }
+                if let Some(task) = parent_stack.tasks.last_mut() {
+                    task.args = args;
+                }
+            }
+            Event::TaskEnd {
+                start,
+                elapsed,
+                capture,
+            } => {
+                if capture != self.capture {
+                    return;
+                }
+
+                // Replace the placeholders with the real start and duration, and add it to its parent's subtasks
+                // Tasks without a parent on their thread become subtasks of the current (or next) frame
+                let parent_stack = self.parent_stacks.entry(thread).or_default();
+                if parent_stack.dropped > 0 {
+                    parent_stack.dropped -= 1;
+                    return;
+                }
+                if let Some(mut task) = parent_stack.tasks.pop() {
+                    task.start = Some(start.saturating_duration_since(self.start));
+                    task.duration = elapsed;
+                    match parent_stack.tasks.last_mut() {
+                        Some(parent) => parent.subtasks.push(task),
+                        None => self.frame_subtasks.push(task),
+                    }
+                }
+            }
+            Event::FrameStart { capture } => {
+                if capture == self.capture {
+                    self.frame_depth += 1;
+                }
+            }
+            Event::FrameEnd {
+                start,
+                elapsed,
+                capture,
+                dropped_tasks,
+            } => {
+                if capture != self.capture {
+                    return;
+                }
+                self.frame_dropped_tasks += dropped_tasks;
+                if self.frame_depth == 0 {
+                    return;
+                }
+
+                // Only the outermost frame is written to the file
+                self.frame_depth -= 1;
+                if self.frame_depth > 0 {
+                    return;
+                }
+
+                self.end_frame(start.saturating_duration_since(self.start), elapsed, thread);
+            }
+            Event::Counter {
+                name,
+                value,
+                capture,
+            } => {
+                if capture != self.capture {
+                    return;
+                }
+                match self.frame_counters.iter_mut().find(|(n, _)| *n == name) {
+                    Some((_, v)) => *v = value,
+                    None => self.frame_counters.push((name, value)),
+                }
+            }
+            Event::Mark {
+                name,
+                payload,
+                time,
+                capture,
+            } => {
+                if capture != self.capture {
+                    return;
+                }
+                self.frame_marks.push(Mark {
+                    name: name.into(),
+                    time: time.saturating_duration_since(self.start),
+                    thread,
+                    payload: payload.map(String::into_boxed_str),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(feature = "profile")]
+impl Capture {
+    /// Write the tasks, counters, and marks recorded since the last frame ended as a frame
+    fn end_frame(&mut self, start: Duration, duration: Duration, thread: u64) {
+        let mut dropped_tasks = mem::take(&mut self.frame_dropped_tasks);
+        if self.max_held_tasks.is_some() {
+            let frame_tasks = count_tasks(&self.frame_subtasks);
+            self.held_tasks = self.held_tasks.saturating_sub(frame_tasks);
+            if dropped_tasks > 0 && self.overflow_policy == OverflowPolicy::DropFrames {
+                dropped_tasks += frame_tasks as u64;
+                DROPPED_TASKS.fetch_add(frame_tasks as u64, Ordering::Relaxed);
+                self.frame_subtasks.clear();
+            }
+        }
+
+        self.frame_number += 1;
+        let frame = TaskDataS {
+            name: TaskNameS::Interned(Rc::from(format!("Frame #{}", self.frame_number))),
+            start: Some(start),
+            duration,
+            thread,
+            args: Vec::new(),
+            subtasks: mem::take(&mut self.frame_subtasks),
+        };
+
+        // Viewers are sent each frame straight away, and disconnected if they can't keep up
+        if !self.viewers.is_empty() {
+            let mut viewer_frame = frame.to_task_data();
+            viewer_frame.dropped_tasks = dropped_tasks;
+            viewer_frame.counters = self
+                .frame_counters
+                .iter()
+                .map(|(name, value)| ((*name).into(), *value))
+                .collect();
+            viewer_frame.marks = self.frame_marks.clone().into();
+            let viewer_frame = Arc::new(viewer_frame);
+            self.viewers
+                .retain(|viewer| viewer.send(ViewerMessage::Frame(Arc::clone(&viewer_frame))));
+        }
+
+        let file = match &mut self.output {
+            CaptureOutput::File(file) => file,
+            CaptureOutput::Recent(recent) => {
+                let frame = RecentFrame {
+                    number: self.frame_number,
+                    frame,
+                    dropped_tasks,
+                    counters: mem::take(&mut self.frame_counters),
+                    marks: mem::take(&mut self.frame_marks),
+                };
+                recent.push(frame, self.capture, &self.metadata);
+                return;
+            }
+        };
+        file.write_frame_record(
+            &frame,
+            dropped_tasks,
+            &self.frame_counters,
+            &self.frame_marks,
+        )
+        .expect("WTF: Failed to write data to file");
+        self.frame_counters.clear();
+        self.frame_marks.clear();
+        if self.last_flush.elapsed() >= self.flush_interval {
+            self.flush().expect("WTF: Failed to write data to file");
+        }
+
+        // Reuse the allocation for the next frame
+        self.frame_subtasks = frame.subtasks;
+        self.frame_subtasks.clear();
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        match &mut self.output {
+            CaptureOutput::File(file) => file.flush(),
+            CaptureOutput::Recent(_) => Ok(()),
+        }
+    }
+
+    /// Start serving frames to a viewer, from the next frame onwards
+    fn add_viewer(&mut self, connection: Connection) {
+        match Viewer::spawn(connection, self.metadata.clone()) {
+            Ok(viewer) => self.viewers.push(viewer),
+            Err(err) => eprintln!("WTF: Failed to serve viewer: {}", err),
+        }
+    }
+
+    /// Flush the remaining frames, and write the frame index to the end of the file. Also ends the viewers' streams, adding
+    /// their threads to `viewer_threads` to be joined before profiling ends
+    ///
+    /// Tasks that finished after the last frame, or during a frame still in progress, are written as a final frame spanning them.
+    /// Recent frames that weren't dumped are discarded, and an automatic dump still waiting for frames after a slow frame ends early
+    fn finish(mut self, viewer_threads: &mut Vec<JoinHandle<()>>) -> io::Result<()> {
+        if !self.frame_subtasks.is_empty()
+            || !self.frame_counters.is_empty()
+            || !self.frame_marks.is_empty()
+            || self.frame_dropped_tasks > 0
+        {
+            let times = self
+                .frame_subtasks
+                .iter()
+                .filter_map(|task| Some((task.start?, task.start? + task.duration)))
+                .chain(self.frame_marks.iter().map(|mark| (mark.time, mark.time)));
+            let start = times
+                .clone()
+                .map(|(start, _)| start)
+                .min()
+                .unwrap_or_default();
+            let end = times.map(|(_, end)| end).max().unwrap_or(start);
+            let thread = match (self.frame_subtasks.first(), self.frame_marks.first()) {
+                (Some(task), _) => task.thread,
+                (None, Some(mark)) => mark.thread,
+                (None, None) => 0,
+            };
+            self.end_frame(start, end - start, thread);
+        }
+
+        viewer_threads.extend(self.viewers.into_iter().map(Viewer::finish));
+        match self.output {
+            CaptureOutput::File(file) => file.finish().map(drop),
+            CaptureOutput::Recent(mut recent) => recent.finish(),
+        }
+    }
+}
+
+#[cfg(feature = "profile")]
+fn count_tasks(tasks: &[TaskDataS]) -> usize {
+    tasks
+        .iter()
+        .map(|task| 1 + count_tasks(&task.subtasks))
+        .sum()
+}
+
+#[cfg(feature = "profile")]
+enum ProfilerMessage {
+    StartCapture {
+        /// `None` if only the capture's recent frames are kept
+        file: Option<Box<ProfileWriter<File>>>,
+        metadata: Vec<(String, String)>,
+        capture: u64,
+        start: Instant,
+    },
+    /// A viewer connected, see [`ProfilerConfig::serve`]
+    Connect(Connection),
+    /// Write the capture's recent frames to the file, see [`Profiler::dump_recent`]
+    DumpRecent {
+        file: File,
+        capture: u64,
+    },
+    StopCapture,
+    Metadata {
+        key: String,
+        value: String,
+    },
+    Events {
+        thread: u64,
+        events: Vec<Event>,
+    },
+    EndProfiling,
+}
+
+/// An event recorded on a thread, sent to the data writing thread as part of a [`ProfilerMessage::Events`] batch
+#[cfg(feature = "profile")]
+enum Event {
+    TaskStart {
+        name: TaskName,
+        capture: u64,
+    },
+    /// Sent just before [`Event::TaskEnd`], if the task has args
+    TaskArgs {
+        args: Vec<(&'static str, ArgValue)>,
+        capture: u64,
+    },
+    TaskEnd {
+        start: Instant,
+        elapsed: Duration,
+        capture: u64,
+    },
+    FrameStart {
+        capture: u64,
+    },
+    FrameEnd {
+        start: Instant,
+        elapsed: Duration,
+        capture: u64,
+        /// The number of tasks dropped by threads during the frame
+        dropped_tasks: u64,
+    },
+    Counter {
+        name: &'static str,
+        value: f64,
+        capture: u64,
+    },
+    Mark {
+        name: &'static str,
+        payload: Option<String>,
+        time: Instant,
+        capture: u64,
+    },
+}
+
+/// A task in progress, returned by [`Profiler::profile_task`]. The task ends when this is dropped.
+pub struct TaskRecord {
+    /// The capture the task was recorded in and when it started, or `None` if the profiler wasn't capturing
+    #[cfg(feature = "profile")]
+    active: Option<(u64, Instant)>,
+    #[cfg(feature = "profile")]
+    args: Vec<(&'static str, ArgValue)>,
+}
+
+impl TaskRecord {
+    #[cfg(feature = "profile")]
+    fn start(name: TaskName) -> Self {
+        let profiler = profiler();
+        let capture = profiler.capture.load(Ordering::Acquire);
+        if capture == 0 || !record_event(profiler, Event::TaskStart { name, capture }) {
+            return Self {
+                active: None,
+                args: Vec::new(),
+            };
+        }
+        Self {
+            active: Some((capture, Instant::now())),
+            args: Vec::new(),
+        }
+    }
+
+    /// Attach a key/value pair to the task, such as the entity being updated or the number of items processed.
+    ///
+    /// Values can be integers, floats, or short strings, and are shown in the GUI when hovering over the task.
+    /// Setting a key that's already set replaces its value.
+    ///
+    /// ```rust,ignore
+    /// let _profile = Profiler::profile_task("load_shader").arg("shader", "water").arg("variants", 12);
+    /// ```
+    #[must_use = "Must assign to a variable: \"_profile = Profiler::profile_task().arg()\""]
+    pub fn arg(mut self, key: &'static str, value: impl Into<ArgValue>) -> Self {
+        self.set_arg(key, value);
+        self
+    }
+
+    /// Like [`TaskRecord::arg`], for values only known after the task has started.
+    #[allow(unused_variables)]
+    pub fn set_arg(&mut self, key: &'static str, value: impl Into<ArgValue>) {
+        #[cfg(feature = "profile")]
+        if self.active.is_some() {
+            let value = value.into();
+            match self.args.iter_mut().find(|(existing, _)| *existing == key) {
+                Some((_, existing)) => *existing = value,
+                None => self.args.push((key, value)),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "profile")]
+impl Drop for TaskRecord {
+    fn drop(&mut self) {
+        if let (Some((capture, start)), Some(profiler)) = (self.active, PROFILER.get()) {
+            let elapsed = start.elapsed();
+            if !self.args.is_empty() {
+                let args = mem::take(&mut self.args);
+                record_event(profiler, Event::TaskArgs { args, capture });
+            }
+            let event = Event::TaskEnd {
+                start,
+                elapsed,
+                capture,
+            };
+            record_event(profiler, event);
+        }
+    }
+}
+
+#[cfg(feature = "profile")]
+pub struct FrameRecord {
+    /// The capture the frame was recorded in and when it started, or `None` if the profiler wasn't capturing
+    active: Option<(u64, Instant)>,
+}
+
+#[cfg(feature = "profile")]
+impl Drop for FrameRecord {
+    fn drop(&mut self) {
+        if let (Some((capture, start)), Some(profiler)) = (self.active, PROFILER.get()) {
+            let event = Event::FrameEnd {
+                start,
+                elapsed: start.elapsed(),
+                capture,
+                dropped_tasks: profiler.frame_dropped_tasks.swap(0, Ordering::Relaxed),
+            };
+            // Send every thread's events before the frame ends, so that the tasks they finished during the frame are part of it
+            send_all_thread_events(profiler, false);
+            record_event(profiler, event);
+            send_thread_events(profiler, false);
+        }
+    }
+}
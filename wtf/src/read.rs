@@ -0,0 +1,847 @@
+use crate::format::{
+    self, CompactFrame, CompactMark, CompactTask, FrameOffset, Header, Record, TaskDataV0,
+};
+use crate::{ArgValue, Error, Mark, ProfileData, TaskData};
+use bincode::Options;
+use serde::de::{self, DeserializeOwned, Deserializer, SeqAccess, Visitor};
+use serde::Deserialize;
+use snap::read::FrameDecoder;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::convert::TryInto;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::iter::Peekable;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::time::Duration;
+use std::vec;
+
+pub fn read_profile_data<R: Read>(reader: R) -> Result<ProfileData, Error> {
+    read_frames(reader, false)
+}
+
+/// Like [`read_profile_data`], but if the profile is cut off or corrupted partway through, the frames before that point are
+/// returned instead of an error.
+///
+/// Useful for profiles from a program that crashed before calling [`Profiler::end_profiling`](crate::Profiler::end_profiling).
+pub fn read_profile_data_lenient<R: Read>(reader: R) -> Result<ProfileData, Error> {
+    read_frames(reader, true)
+}
+
+fn read_frames<R: Read>(reader: R, lenient: bool) -> Result<ProfileData, Error> {
+    let mut frames = Vec::new();
+    for frame in ProfileReader::new(reader)? {
+        match frame {
+            Ok(frame) => frames.push(frame),
+            Err(
+                Error::TruncatedFrame { .. }
+                | Error::Decompression { .. }
+                | Error::CorruptFrame { .. },
+            ) if lenient => break,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(frames.into_boxed_slice())
+}
+
+/// Reads the frames of a profile one at a time, so that the whole profile doesn't need to fit in memory.
+///
+/// ```rust,ignore
+/// let file = BufReader::new(File::open("game.wtf")?);
+/// let mut reader = ProfileReader::new(file)?;
+/// reader.skip_frames(1000)?;
+/// for frame in reader {
+///     let frame = frame?;
+///     println!("{}: {:?}", frame.name, frame.duration);
+/// }
+/// ```
+///
+/// After returning an error, the iterator ends.
+pub struct ProfileReader<R: Read> {
+    records: RecordReader<io::Chain<io::Cursor<Vec<u8>>, R>>,
+    version: u32,
+    /// The number of frames read or skipped so far
+    frame: usize,
+    done: bool,
+    /// The string table read so far, or all of it if reading from partway through the profile
+    names: Rc<Vec<Box<str>>>,
+    /// Like `names`, the metadata read so far or all of it
+    metadata: Rc<Vec<(String, String)>>,
+}
+
+impl<R: Read> ProfileReader<R> {
+    /// Read the start of the profile, returning an error if it isn't a supported profile.
+    ///
+    /// Reading is done in small pieces, so `reader` should be buffered, e.g. with a [`BufReader`](std::io::BufReader).
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        // Older profiles don't have a header, so check the first few bytes to tell which layout the file uses
+        let mut prefix = Vec::with_capacity(format::MAGIC.len());
+        (&mut reader)
+            .take(format::MAGIC.len() as u64)
+            .read_to_end(&mut prefix)?;
+
+        if prefix == format::MAGIC {
+            let mut version = [0; 4];
+            reader
+                .read_exact(&mut version)
+                .map_err(|err| match err.kind() {
+                    io::ErrorKind::UnexpectedEof => Error::CorruptHeader,
+                    _ => Error::Io(err),
+                })?;
+            let offset = (format::MAGIC.len() + version.len()) as u64;
+            let version = u32::from_le_bytes(version);
+            if !(1..=format::VERSION).contains(&version) {
+                return Err(Error::UnsupportedVersion { version });
+            }
+
+            let mut records = RecordReader::new(io::Cursor::new(Vec::new()).chain(reader), offset);
+            let header: Header = match records.read(0) {
+                Ok(Some(header)) => header,
+                Err(Error::Io(err)) => return Err(Error::Io(err)),
+                _ => return Err(Error::CorruptHeader),
+            };
+            records.compact = version >= 2;
+            Ok(Self {
+                records,
+                version,
+                frame: 0,
+                done: false,
+                names: Rc::default(),
+                metadata: Rc::new(header.metadata),
+            })
+        } else if prefix.is_empty() || prefix == format::SNAPPY_STREAM_IDENTIFIER {
+            Ok(Self {
+                records: RecordReader::new(io::Cursor::new(prefix).chain(reader), 0),
+                version: 0,
+                frame: 0,
+                done: false,
+                names: Rc::default(),
+                metadata: Rc::default(),
+            })
+        } else {
+            Err(Error::NotAProfile)
+        }
+    }
+
+    /// Start reading partway through a profile, with `reader` at the start of the chunk `offset` refers to
+    fn at(
+        reader: R,
+        version: u32,
+        offset: FrameOffset,
+        frame: usize,
+        names: Rc<Vec<Box<str>>>,
+        metadata: Rc<Vec<(String, String)>>,
+    ) -> Result<Self, Error> {
+        // The decoder expects a stream identifier before any other chunk
+        let header = format::SNAPPY_STREAM_HEADER;
+        let records_offset = offset.chunk.saturating_sub(header.len() as u64);
+        let mut records = RecordReader::new(
+            io::Cursor::new(header.to_vec()).chain(reader),
+            records_offset,
+        );
+        records.compact = version >= 2;
+        records.skip(offset.skip, frame)?;
+        Ok(Self {
+            records,
+            version,
+            frame,
+            done: false,
+            names,
+            metadata,
+        })
+    }
+
+    /// The key/value pairs describing the profile, such as the program name, start time, and anything set with
+    /// [`Profiler::set_metadata`](crate::Profiler::set_metadata). Includes metadata set while recording only once it's been read.
+    pub fn metadata(&self) -> &[(String, String)] {
+        &self.metadata
+    }
+
+    /// Skip over the next frame without storing it, which is faster than reading it. Returns false if there are no more frames.
+    pub fn skip_frame(&mut self) -> Result<bool, Error> {
+        let skipped = match self.version {
+            0 => self.read_frame::<SkippedTaskV0>()?.is_some(),
+            1 => self.read_frame::<SkippedTask>()?.is_some(),
+            _ => self.read_frame::<SkippedCompactFrame>()?.is_some(),
+        };
+        Ok(skipped)
+    }
+
+    /// Skip over up to `count` frames, returning how many were skipped. This is less than `count` only if the profile ended.
+    pub fn skip_frames(&mut self, count: usize) -> Result<usize, Error> {
+        for skipped in 0..count {
+            if !self.skip_frame()? {
+                return Ok(skipped);
+            }
+        }
+        Ok(count)
+    }
+
+    /// Read records up to and including the next frame, returning it and the number of tasks dropped from it
+    fn read_frame<T: DeserializeOwned>(&mut self) -> Result<Option<(T, FrameRecords)>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let result = self.read_records();
+        match &result {
+            Ok(Some(_)) => self.frame += 1,
+            _ => self.done = true,
+        }
+        result
+    }
+
+    fn read_records<T: DeserializeOwned>(&mut self) -> Result<Option<(T, FrameRecords)>, Error> {
+        if self.version == 0 {
+            let frame = self.records.read(self.frame)?;
+            return Ok(frame.map(|frame| (frame, FrameRecords::default())));
+        }
+
+        let mut records = FrameRecords::default();
+        loop {
+            match self.records.read(self.frame)? {
+                Some(Record::Frame(frame)) => return Ok(Some((frame, records))),
+                Some(Record::DroppedTasks(count)) => records.dropped_tasks = count,
+                Some(Record::Counter(id, value)) => records.counters.push((self.name(id)?, value)),
+                Some(Record::Mark(mark)) => records.marks.push((self.name(mark.name)?, mark)),
+                Some(Record::Args(task, args)) => {
+                    let args = args
+                        .into_iter()
+                        .map(|(name, value)| Ok((self.name(name)?, value)))
+                        .collect::<Result<_, Error>>()?;
+                    records.args.push((task, args));
+                }
+                Some(Record::FrameArgs(args)) => {
+                    records.frame_args = args
+                        .into_iter()
+                        .map(|(name, value)| Ok((self.name(name)?, value)))
+                        .collect::<Result<_, Error>>()?;
+                }
+                Some(Record::Name(id, name)) => self.add_name(id, name)?,
+                Some(Record::Metadata(key, value)) => {
+                    format::set_metadata(Rc::make_mut(&mut self.metadata), key, value)
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn add_name(&mut self, id: u32, name: Box<str>) -> Result<(), Error> {
+        let id = id as usize;
+        match id.cmp(&self.names.len()) {
+            Ordering::Equal => Rc::make_mut(&mut self.names).push(name),
+            // Already known from the string table at the end of the profile
+            Ordering::Less => {}
+            Ordering::Greater => {
+                return Err(Error::CorruptFrame {
+                    frame: self.frame,
+                    offset: self.records.offset(),
+                    source: "Task name added to the string table out of order".into(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Look up a name in the string table
+    fn name(&self, id: u32) -> Result<Box<str>, Error> {
+        self.names
+            .get(id as usize)
+            .cloned()
+            .ok_or_else(|| Error::CorruptFrame {
+                frame: self.frame,
+                offset: self.records.offset(),
+                source: "Name not in the string table".into(),
+            })
+    }
+
+    /// Convert a frame read by [`ProfileReader::read_frame`] to a [`TaskData`]
+    fn expand(&self, frame: CompactFrame, records: FrameRecords) -> Result<TaskData, Error> {
+        let corrupt = |source: &str| Error::CorruptFrame {
+            frame: self.frame - 1,
+            offset: self.records.offset(),
+            source: source.into(),
+        };
+        let start = frame.start.map(Duration::from_nanos);
+        let mut tasks = TaskExpander {
+            names: &self.names,
+            next_task: 0,
+            args: records.args.into_iter().peekable(),
+        };
+        let subtasks = frame
+            .subtasks
+            .into_iter()
+            .map(|task| tasks.expand(task, start))
+            .collect::<Option<_>>()
+            .ok_or_else(|| corrupt("Task has an unknown name or an invalid start"))?;
+        let marks = records
+            .marks
+            .into_iter()
+            .map(|(name, mark)| {
+                Some(Mark {
+                    name,
+                    time: format::decode_start(mark.time, start)?,
+                    thread: mark.thread,
+                    payload: mark.payload,
+                })
+            })
+            .collect::<Option<_>>()
+            .ok_or_else(|| corrupt("Mark has an invalid time"))?;
+        Ok(TaskData {
+            name: frame.name,
+            start,
+            duration: Duration::from_nanos(frame.duration),
+            thread: frame.thread,
+            dropped_tasks: records.dropped_tasks,
+            counters: records.counters.into(),
+            marks,
+            args: records.frame_args,
+            subtasks,
+        })
+    }
+}
+
+/// Converts the tasks of a frame to [`TaskData`]s, in the same depth first order they're counted in for [`Record::Args`]
+struct TaskExpander<'a> {
+    names: &'a [Box<str>],
+    /// The index of the next task in the frame
+    next_task: u32,
+    /// The args of each task with args, ordered by the task's index
+    args: Peekable<vec::IntoIter<(u32, TaskArgs)>>,
+}
+
+impl TaskExpander<'_> {
+    fn expand(&mut self, task: CompactTask, parent_start: Option<Duration>) -> Option<TaskData> {
+        let index = self.next_task;
+        self.next_task += 1;
+        let args = match self.args.next_if(|(task, _)| *task == index) {
+            Some((_, args)) => args,
+            None => Box::default(),
+        };
+
+        let start = match task.start {
+            Some(start) => Some(format::decode_start(start, parent_start)?),
+            None => None,
+        };
+        let subtasks = task
+            .subtasks
+            .into_iter()
+            .map(|subtask| self.expand(subtask, start))
+            .collect::<Option<_>>()?;
+        Some(TaskData {
+            name: self.names.get(task.name as usize)?.clone(),
+            start,
+            duration: Duration::from_nanos(task.duration),
+            thread: task.thread,
+            dropped_tasks: 0,
+            counters: Box::default(),
+            marks: Box::default(),
+            args,
+            subtasks,
+        })
+    }
+}
+
+impl<R: Read> Iterator for ProfileReader<R> {
+    type Item = Result<TaskData, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = match self.version {
+            0 => self
+                .read_frame::<TaskDataV0>()
+                .map(|frame| frame.map(|(frame, _)| TaskData::from(frame))),
+            1 => self.read_frame::<TaskData>().map(|frame| {
+                frame.map(|(mut frame, records)| {
+                    frame.dropped_tasks = records.dropped_tasks;
+                    frame
+                })
+            }),
+            _ => self.read_frame::<CompactFrame>().and_then(|frame| {
+                frame
+                    .map(|(frame, records)| self.expand(frame, records))
+                    .transpose()
+            }),
+        };
+        frame.transpose()
+    }
+}
+
+/// The records that come before a frame and add to it
+#[derive(Default)]
+struct FrameRecords {
+    dropped_tasks: u64,
+    counters: Vec<(Box<str>, f64)>,
+    /// Marks with their names looked up, and times not yet decoded
+    marks: Vec<(Box<str>, CompactMark)>,
+    /// The args of each task with args, with their names looked up
+    args: Vec<(u32, TaskArgs)>,
+    frame_args: TaskArgs,
+}
+
+/// The args of one task, with their names looked up
+type TaskArgs = Box<[(Box<str>, ArgValue)]>;
+
+/// A profile opened for reading any of its frames, without reading the frames before it.
+///
+/// ```rust,ignore
+/// let mut profile = Profile::open(BufReader::new(File::open("game.wtf")?))?;
+/// let frame = profile.frame(500_000)?;
+/// ```
+///
+/// Profiles hold an index of where each frame starts, which is written when the capture is stopped. If a profile doesn't have one,
+/// e.g. because it was written by an older version or the program crashed, the whole profile is scanned to rebuild the index.
+pub struct Profile<R: Read + Seek> {
+    reader: R,
+    version: u32,
+    index: Vec<FrameOffset>,
+    names: Rc<Vec<Box<str>>>,
+    metadata: Rc<Vec<(String, String)>>,
+}
+
+impl<R: Read + Seek> Profile<R> {
+    /// Open a profile and read its frame index, returning an error if it isn't a supported profile.
+    ///
+    /// Reading is done in small pieces, so `reader` should be buffered, e.g. with a [`BufReader`](std::io::BufReader).
+    pub fn open(mut reader: R) -> Result<Self, Error> {
+        reader.rewind()?;
+        let ProfileReader {
+            version, metadata, ..
+        } = ProfileReader::new(&mut reader)?;
+        let index = match read_index(&mut reader)? {
+            Some(index) => {
+                // The index only holds metadata set after the header
+                let mut metadata = (*metadata).clone();
+                for (key, value) in index.metadata {
+                    format::set_metadata(&mut metadata, key, value);
+                }
+                Index { metadata, ..index }
+            }
+            None => {
+                reader.rewind()?;
+                build_index(ProfileReader::new(&mut reader)?)?
+            }
+        };
+        Ok(Self {
+            reader,
+            version,
+            index: index.frames,
+            names: Rc::new(index.names),
+            metadata: Rc::new(index.metadata),
+        })
+    }
+
+    /// The key/value pairs describing the profile, such as the program name, start time, and anything set with
+    /// [`Profiler::set_metadata`](crate::Profiler::set_metadata).
+    pub fn metadata(&self) -> &[(String, String)] {
+        &self.metadata
+    }
+
+    /// The number of frames in the profile.
+    pub fn frame_count(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Read frame `n`, counting from 0, or return `None` if there are fewer frames than that.
+    pub fn frame(&mut self, n: usize) -> Result<Option<TaskData>, Error> {
+        self.frames_from(n)?.next().transpose()
+    }
+
+    /// Read the frames from frame `n` onwards, one at a time.
+    pub fn frames_from(&mut self, n: usize) -> Result<ProfileReader<&mut R>, Error> {
+        match self.index.get(n) {
+            Some(&offset) => {
+                self.reader.seek(SeekFrom::Start(offset.chunk))?;
+                let (names, metadata) = (Rc::clone(&self.names), Rc::clone(&self.metadata));
+                ProfileReader::at(&mut self.reader, self.version, offset, n, names, metadata)
+            }
+            None => {
+                let end = self.reader.seek(SeekFrom::End(0))?;
+                let offset = FrameOffset {
+                    chunk: end,
+                    skip: 0,
+                };
+                let (names, metadata) = (Rc::clone(&self.names), Rc::clone(&self.metadata));
+                let mut frames =
+                    ProfileReader::at(&mut self.reader, self.version, offset, n, names, metadata)?;
+                frames.done = true;
+                Ok(frames)
+            }
+        }
+    }
+}
+
+/// The parts of a profile read by [`Profile::open`]
+struct Index {
+    frames: Vec<FrameOffset>,
+    names: Vec<Box<str>>,
+    metadata: Vec<(String, String)>,
+}
+
+/// Read the frame index, string table, and metadata set after the header from the end of the profile,
+/// or return `None` if there isn't a complete index
+fn read_index<R: Read + Seek>(reader: &mut R) -> Result<Option<Index>, Error> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    if len < format::INDEX_TRAILER_LEN as u64 {
+        return Ok(None);
+    }
+    let trailer_start = len - format::INDEX_TRAILER_LEN as u64;
+    reader.seek(SeekFrom::Start(trailer_start))?;
+    let mut trailer = [0; format::INDEX_TRAILER_LEN];
+    reader.read_exact(&mut trailer)?;
+    let trailer_header = format::chunk_header(
+        format::INDEX_TRAILER_CHUNK_TYPE,
+        format::INDEX_TRAILER_LEN - 4,
+    );
+    if trailer[..4] != trailer_header || trailer[4..12] != format::INDEX_TRAILER_MAGIC {
+        return Ok(None);
+    }
+    let index_start = u64::from_le_bytes(trailer[12..20].try_into().unwrap());
+    let frame_count = u64::from_le_bytes(trailer[20..28].try_into().unwrap());
+    if index_start > trailer_start || frame_count > (trailer_start - index_start) / 16 {
+        return Ok(None);
+    }
+
+    reader.seek(SeekFrom::Start(index_start))?;
+    let mut chunks = Vec::new();
+    reader
+        .take(trailer_start - index_start)
+        .read_to_end(&mut chunks)?;
+
+    let mut frames = Vec::with_capacity(frame_count as usize);
+    let mut names = Vec::new();
+    let mut metadata = Vec::new();
+    let mut chunks = &chunks[..];
+    while !chunks.is_empty() {
+        if chunks.len() < 4 {
+            return Ok(None);
+        }
+        let chunk_len = u32::from_le_bytes([chunks[1], chunks[2], chunks[3], 0]) as usize;
+        let chunk = match chunks.get(4..4 + chunk_len) {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+        match chunks[0] {
+            format::INDEX_CHUNK_TYPE if chunk_len % 16 == 0 => {
+                frames.extend(chunk.chunks_exact(16).map(|entry| FrameOffset {
+                    chunk: u64::from_le_bytes(entry[..8].try_into().unwrap()),
+                    skip: u64::from_le_bytes(entry[8..].try_into().unwrap()),
+                }))
+            }
+            format::INDEX_NAMES_CHUNK_TYPE => names.extend_from_slice(chunk),
+            format::INDEX_METADATA_CHUNK_TYPE => metadata.extend_from_slice(chunk),
+            _ => return Ok(None),
+        }
+        chunks = &chunks[4 + chunk_len..];
+    }
+    if frames.len() as u64 != frame_count {
+        return Ok(None);
+    }
+
+    match (read_table(&names), read_table(&metadata)) {
+        (Some(names), Some(metadata)) => Ok(Some(Index {
+            frames,
+            names,
+            metadata,
+        })),
+        _ => Ok(None),
+    }
+}
+
+/// Read consecutive values from a string table or metadata chunk, or return `None` if they're damaged
+fn read_table<T: DeserializeOwned>(mut table: &[u8]) -> Option<Vec<T>> {
+    let mut values = Vec::new();
+    while !table.is_empty() {
+        values.push(format::record_options().deserialize_from(&mut table).ok()?);
+    }
+    Some(values)
+}
+
+/// Scan the profile for where each frame starts, and for its string table and metadata, stopping at the first damaged frame
+fn build_index<R: Read>(mut frames: ProfileReader<R>) -> Result<Index, Error> {
+    let mut index = Vec::new();
+    loop {
+        let offset = frames.records.next_offset();
+        match frames.skip_frame() {
+            Ok(true) => index.push(offset),
+            Ok(false)
+            | Err(
+                Error::TruncatedFrame { .. }
+                | Error::Decompression { .. }
+                | Error::CorruptFrame { .. },
+            ) => {
+                return Ok(Index {
+                    frames: index,
+                    names: Rc::try_unwrap(frames.names).unwrap_or_else(|names| (*names).clone()),
+                    metadata: Rc::try_unwrap(frames.metadata)
+                        .unwrap_or_else(|metadata| (*metadata).clone()),
+                })
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Reads bincode values out of a snappy frame stream, keeping track of how much data has been read in order to tell
+/// the end of the stream apart from a truncated one
+struct RecordReader<R: Read> {
+    decoder: DecompressedReader<FrameDecoder<ChunkReader<R>>>,
+    /// Whether values are encoded with [`format::record_options`]
+    compact: bool,
+}
+
+impl<R: Read> RecordReader<R> {
+    /// `offset` is the number of bytes of the profile that came before `reader`
+    fn new(reader: R, offset: u64) -> Self {
+        let decompressed = Rc::new(Cell::new(0));
+        let reader = ChunkReader {
+            inner: reader,
+            count: offset,
+            decompressed: Rc::clone(&decompressed),
+            header: [0; 4],
+            header_len: 0,
+            chunk_remaining: 0,
+            chunk_start: (offset, 0),
+        };
+        Self {
+            decoder: DecompressedReader {
+                inner: FrameDecoder::new(reader),
+                count: decompressed,
+            },
+            compact: false,
+        }
+    }
+
+    /// The number of compressed bytes read so far
+    fn offset(&self) -> u64 {
+        self.decoder.inner.get_ref().count
+    }
+
+    /// Where the next value starts
+    fn next_offset(&self) -> FrameOffset {
+        let (chunk, decompressed) = self.decoder.inner.get_ref().chunk_start;
+        FrameOffset {
+            chunk,
+            skip: self.decoder.count.get() - decompressed,
+        }
+    }
+
+    /// Skip over `count` decompressed bytes
+    fn skip(&mut self, count: u64, frame: usize) -> Result<(), Error> {
+        match io::copy(&mut (&mut self.decoder).take(count), &mut io::sink()) {
+            Ok(skipped) if skipped == count => Ok(()),
+            Ok(_) => Err(Error::TruncatedFrame {
+                frame,
+                offset: self.offset(),
+            }),
+            Err(err) => Err(self.io_error(err, frame)),
+        }
+    }
+
+    /// Read the next value, or return `None` if the stream ended cleanly before it
+    fn read<T: DeserializeOwned>(&mut self, frame: usize) -> Result<Option<T>, Error> {
+        let decompressed_start = self.decoder.count.get();
+
+        let value = if self.compact {
+            format::record_options().deserialize_from(&mut self.decoder)
+        } else {
+            bincode::deserialize_from(&mut self.decoder)
+        };
+        let err = match value {
+            Ok(value) => return Ok(Some(value)),
+            Err(err) => err,
+        };
+        match *err {
+            bincode::ErrorKind::Io(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                // Ending between values is fine, but ending partway through a value or a chunk means the profile was cut off
+                let chunks = self.decoder.inner.get_ref();
+                if self.decoder.count.get() == decompressed_start && chunks.at_chunk_start() {
+                    Ok(None)
+                } else {
+                    Err(Error::TruncatedFrame {
+                        frame,
+                        offset: self.offset(),
+                    })
+                }
+            }
+            bincode::ErrorKind::Io(err) => Err(self.io_error(err, frame)),
+            err => Err(Error::CorruptFrame {
+                frame,
+                offset: self.offset(),
+                source: Box::new(err),
+            }),
+        }
+    }
+
+    fn io_error(&self, err: io::Error, frame: usize) -> Error {
+        if err.get_ref().is_some_and(|err| err.is::<snap::Error>()) {
+            Error::Decompression {
+                frame,
+                offset: self.offset(),
+                source: err,
+            }
+        } else {
+            Error::Io(err)
+        }
+    }
+}
+
+/// Counts the decompressed bytes read
+struct DecompressedReader<R: Read> {
+    inner: R,
+    /// Shared with the [`ChunkReader`] underneath
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for DecompressedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.set(self.count.get() + read as u64);
+        Ok(read)
+    }
+}
+
+/// Counts the compressed bytes read, and keeps track of where the latest snappy chunk started
+struct ChunkReader<R: Read> {
+    inner: R,
+    count: u64,
+    /// The number of decompressed bytes read so far
+    decompressed: Rc<Cell<u64>>,
+    header: [u8; 4],
+    /// The number of bytes of the current chunk's header read so far
+    header_len: usize,
+    /// The number of bytes of the current chunk left to read after its header
+    chunk_remaining: u64,
+    /// Where the latest chunk started, and the number of decompressed bytes read before it
+    ///
+    /// The decoder only starts a new chunk once everything before it has been read, so this lines up with the decompressed data
+    chunk_start: (u64, u64),
+}
+
+impl<R: Read> ChunkReader<R> {
+    fn at_chunk_start(&self) -> bool {
+        self.header_len == 0
+    }
+}
+
+impl<R: Read> Read for ChunkReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        let mut parsed = 0;
+        while parsed < read {
+            if self.header_len < self.header.len() {
+                if self.header_len == 0 {
+                    self.chunk_start = (self.count + parsed as u64, self.decompressed.get());
+                }
+                self.header[self.header_len] = buf[parsed];
+                self.header_len += 1;
+                parsed += 1;
+                if self.header_len == self.header.len() {
+                    self.chunk_remaining =
+                        u32::from_le_bytes([self.header[1], self.header[2], self.header[3], 0])
+                            as u64;
+                }
+            } else {
+                let body = self.chunk_remaining.min((read - parsed) as u64);
+                self.chunk_remaining -= body;
+                parsed += body as usize;
+            }
+            if self.header_len == self.header.len() && self.chunk_remaining == 0 {
+                self.header_len = 0;
+            }
+        }
+
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+/// Deserializes a task and all of its subtasks without storing them, in order to skip over frames quickly
+///
+/// The fields must match [`TaskData`]
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct SkippedTask {
+    name: SkippedStr,
+    start: Option<Duration>,
+    duration: Duration,
+    thread: u64,
+    subtasks: SkippedSubtasks<SkippedTask>,
+}
+
+/// Like [`SkippedTask`], but matching [`CompactFrame`]
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct SkippedCompactFrame {
+    name: SkippedStr,
+    start: Option<u64>,
+    duration: u64,
+    thread: u64,
+    subtasks: SkippedSubtasks<SkippedCompactTask>,
+}
+
+/// Like [`SkippedTask`], but matching [`CompactTask`]
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct SkippedCompactTask {
+    name: u32,
+    start: Option<i64>,
+    duration: u64,
+    thread: u64,
+    subtasks: SkippedSubtasks<SkippedCompactTask>,
+}
+
+/// Like [`SkippedTask`], but matching [`TaskDataV0`]
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct SkippedTaskV0 {
+    name: SkippedStr,
+    duration: Duration,
+    subtasks: SkippedSubtasks<SkippedTaskV0>,
+}
+
+struct SkippedStr;
+
+impl<'de> Deserialize<'de> for SkippedStr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SkippedStrVisitor;
+
+        impl<'de> Visitor<'de> for SkippedStrVisitor {
+            type Value = SkippedStr;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string")
+            }
+
+            fn visit_str<E: de::Error>(self, _: &str) -> Result<Self::Value, E> {
+                Ok(SkippedStr)
+            }
+        }
+
+        deserializer.deserialize_str(SkippedStrVisitor)
+    }
+}
+
+struct SkippedSubtasks<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for SkippedSubtasks<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SkippedSubtasksVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for SkippedSubtasksVisitor<T> {
+            type Value = SkippedSubtasks<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a sequence of tasks")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                while seq.next_element::<T>()?.is_some() {}
+                Ok(SkippedSubtasks(PhantomData))
+            }
+        }
+
+        deserializer.deserialize_seq(SkippedSubtasksVisitor(PhantomData))
+    }
+}
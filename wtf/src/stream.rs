@@ -0,0 +1,197 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{AddrParseError, SocketAddr, TcpStream};
+use std::str::FromStr;
+
+#[cfg(unix)]
+use {std::os::unix::net::UnixStream, std::path::PathBuf};
+
+#[cfg(feature = "profile")]
+use {
+    std::net::{Ipv4Addr, Ipv6Addr, TcpListener},
+    std::time::Duration,
+};
+
+#[cfg(all(feature = "profile", unix))]
+use {std::fs, std::os::unix::fs::FileTypeExt, std::os::unix::net::UnixListener};
+
+/// How long a viewer's thread waits for it to accept data before disconnecting it, so that a stuck viewer can't delay the end
+/// of profiling
+#[cfg(feature = "profile")]
+const VIEWER_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where the profiler serves frames to viewers while capturing. See [`ProfilerConfig::serve`](crate::ProfilerConfig::serve).
+///
+/// Parsed from either a socket address such as `127.0.0.1:7777`, or `unix:` followed by the path of a Unix socket.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServeAddress {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl FromStr for ServeAddress {
+    type Err = AddrParseError;
+
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        #[cfg(unix)]
+        if let Some(path) = address.strip_prefix("unix:") {
+            return Ok(ServeAddress::Unix(PathBuf::from(path)));
+        }
+        address.parse().map(ServeAddress::Tcp)
+    }
+}
+
+impl From<SocketAddr> for ServeAddress {
+    fn from(address: SocketAddr) -> Self {
+        ServeAddress::Tcp(address)
+    }
+}
+
+#[cfg(feature = "profile")]
+impl ServeAddress {
+    /// Remove the Unix socket at this address, but nothing else that happens to be at its path. Does nothing for TCP addresses
+    pub(crate) fn remove_socket(&self) -> io::Result<()> {
+        match self {
+            ServeAddress::Tcp(_) => Ok(()),
+            #[cfg(unix)]
+            ServeAddress::Unix(path) => {
+                if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket())
+                {
+                    fs::remove_file(path)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for ServeAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServeAddress::Tcp(address) => address.fmt(f),
+            #[cfg(unix)]
+            ServeAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A connection to a program being profiled, which streams the frames of its current or next capture as they finish.
+///
+/// The stream is a `.wtf` profile, so it's read with a [`ProfileReader`](crate::ProfileReader), which returns each frame as it
+/// arrives, and ends when the capture stops.
+///
+/// ```rust,ignore
+/// let connection = Connection::connect(&"127.0.0.1:7777".parse()?)?;
+/// for frame in ProfileReader::new(BufReader::new(connection))? {
+///     let frame = frame?;
+///     println!("{}: {:?}", frame.name, frame.duration);
+/// }
+/// ```
+pub enum Connection {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Connection {
+    /// Connect to a program being profiled with [`ProfilerConfig::serve`](crate::ProfilerConfig::serve) set to `address`.
+    pub fn connect(address: &ServeAddress) -> io::Result<Self> {
+        match address {
+            ServeAddress::Tcp(address) => TcpStream::connect(address).map(Connection::Tcp),
+            #[cfg(unix)]
+            ServeAddress::Unix(path) => UnixStream::connect(path).map(Connection::Unix),
+        }
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Accepts connections from viewers
+#[cfg(feature = "profile")]
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+#[cfg(feature = "profile")]
+impl Listener {
+    pub(crate) fn bind(address: &ServeAddress) -> io::Result<Self> {
+        match address {
+            ServeAddress::Tcp(address) => TcpListener::bind(address).map(Listener::Tcp),
+            #[cfg(unix)]
+            ServeAddress::Unix(path) => {
+                // Remove the socket left behind by a previous run
+                address.remove_socket()?;
+                UnixListener::bind(path).map(Listener::Unix)
+            }
+        }
+    }
+
+    /// The address to connect to in order to reach this listener, such as to wake the thread waiting in [`Listener::accept`]
+    pub(crate) fn local_address(&self) -> io::Result<ServeAddress> {
+        match self {
+            Listener::Tcp(listener) => {
+                let mut address = listener.local_addr()?;
+                // Listening on every interface includes the loopback interface
+                if address.ip().is_unspecified() {
+                    address.set_ip(match address {
+                        SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                        SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+                    });
+                }
+                Ok(ServeAddress::Tcp(address))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => match listener.local_addr()?.as_pathname() {
+                Some(path) => Ok(ServeAddress::Unix(path.to_path_buf())),
+                None => Err(io::Error::other("WTF: Unix socket has no path")),
+            },
+        }
+    }
+
+    /// Wait for the next viewer to connect
+    pub(crate) fn accept(&self) -> io::Result<Connection> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                stream.set_write_timeout(Some(VIEWER_WRITE_TIMEOUT))?;
+                Ok(Connection::Tcp(stream))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_write_timeout(Some(VIEWER_WRITE_TIMEOUT))?;
+                Ok(Connection::Unix(stream))
+            }
+        }
+    }
+}
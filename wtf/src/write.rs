@@ -0,0 +1,447 @@
+use crate::format::{self, CompactMark, FrameOffset, Header, Record};
+use crate::{ArgValue, Mark, TaskData};
+use bincode::Options;
+use serde::ser::{Serialize, SerializeSeq, SerializeStruct, Serializer};
+use snap::write::FrameEncoder;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// The number of bytes of serialized frames buffered before they're compressed and written, even if not flushed
+const PENDING_CAPACITY: usize = 1 << 16;
+
+/// Writes frames to a `.wtf` profile, which can then be read with [`read_profile_data`](crate::read_profile_data).
+///
+/// Useful for building profiles in tests, converting other trace formats, or saving a filtered copy of a profile.
+///
+/// ```rust,ignore
+/// let mut writer = ProfileWriter::new(File::create("filtered.wtf")?)?;
+/// for frame in read_profile_data(File::open("game.wtf")?)?.iter() {
+///     if frame.duration > Duration::from_millis(16) {
+///         writer.write_frame(frame)?;
+///     }
+/// }
+/// writer.finish()?;
+/// ```
+///
+/// Frames are buffered, and compressed in batches. [`ProfileWriter::finish`] must be called once all frames are written,
+/// to write the frame index used by [`Profile`](crate::Profile). If the writer is dropped instead, the frames are still written,
+/// but any errors are ignored.
+pub struct ProfileWriter<W: Write> {
+    /// Only `None` once finished
+    encoder: Option<FrameEncoder<CountingWriter<W>>>,
+    /// Records that haven't been compressed and written yet
+    pending: Vec<u8>,
+    /// Where each frame in `pending` starts
+    pending_frames: Vec<u64>,
+    frame_index: Vec<FrameOffset>,
+    /// The string table, mapping each name written so far to its id
+    names: HashMap<Box<str>, u32>,
+    /// The name ids of the frame being written, in the order they're serialized
+    frame_names: Vec<u32>,
+    /// Metadata set after the header was written
+    metadata: Vec<(String, String)>,
+}
+
+impl<W: Write> ProfileWriter<W> {
+    /// Start a new profile, writing its header.
+    pub fn new(writer: W) -> io::Result<Self> {
+        let metadata = vec![(
+            "wtf_version".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+        )];
+        Self::with_metadata(writer, metadata)
+    }
+
+    pub(crate) fn with_metadata(writer: W, metadata: Vec<(String, String)>) -> io::Result<Self> {
+        let mut writer = CountingWriter {
+            inner: writer,
+            count: 0,
+        };
+        writer.write_all(&format::MAGIC)?;
+        writer.write_all(&format::VERSION.to_le_bytes())?;
+
+        let mut encoder = FrameEncoder::new(writer);
+        bincode::serialize_into(&mut encoder, &Header { metadata }).map_err(io::Error::other)?;
+        // Frames are written starting at a new chunk, so that the frame index can point to them
+        encoder.flush()?;
+
+        Ok(Self {
+            encoder: Some(encoder),
+            pending: Vec::new(),
+            pending_frames: Vec::new(),
+            frame_index: Vec::new(),
+            names: HashMap::new(),
+            frame_names: Vec::new(),
+            metadata: Vec::new(),
+        })
+    }
+
+    /// Add a frame to the end of the profile.
+    pub fn write_frame(&mut self, frame: &TaskData) -> io::Result<()> {
+        self.write_frame_record(frame, frame.dropped_tasks, &frame.counters, &frame.marks)
+    }
+
+    /// Set a metadata key, such as the game's version or graphics settings, replacing any previous value.
+    ///
+    /// The metadata can be read back with [`Profile::metadata`](crate::Profile::metadata).
+    pub fn set_metadata(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> io::Result<()> {
+        let (key, value) = (key.into(), value.into());
+        format::record_options()
+            .serialize_into(
+                &mut self.pending,
+                &Record::<()>::Metadata(key.clone(), value.clone()),
+            )
+            .map_err(io::Error::other)?;
+        format::set_metadata(&mut self.metadata, key, value);
+        Ok(())
+    }
+
+    /// Write a frame of any task type
+    pub(crate) fn write_frame_record<T: WriteTask, C: AsRef<str>>(
+        &mut self,
+        frame: &T,
+        dropped_tasks: u64,
+        counters: &[(C, f64)],
+        marks: &[Mark],
+    ) -> io::Result<()> {
+        self.pending_frames.push(self.pending.len() as u64);
+
+        // Add any new names to the string table before the frame that uses them
+        self.frame_names.clear();
+        for task in frame.subtasks() {
+            self.intern_names(task)?;
+        }
+        if !frame.args().is_empty() {
+            let args = self.intern_args(frame.args())?;
+            format::record_options()
+                .serialize_into(&mut self.pending, &Record::<()>::FrameArgs(args))
+                .map_err(io::Error::other)?;
+        }
+        for (name, value) in counters {
+            let id = self.intern_name(name.as_ref())?;
+            format::record_options()
+                .serialize_into(&mut self.pending, &Record::<()>::Counter(id, *value))
+                .map_err(io::Error::other)?;
+        }
+        for mark in marks {
+            let mark = CompactMark {
+                name: self.intern_name(&mark.name)?,
+                time: format::encode_start(Some(mark.time), frame.start()).unwrap_or_default(),
+                thread: mark.thread,
+                payload: mark.payload.clone(),
+            };
+            format::record_options()
+                .serialize_into(&mut self.pending, &Record::<()>::Mark(mark))
+                .map_err(io::Error::other)?;
+        }
+
+        if dropped_tasks > 0 {
+            format::record_options()
+                .serialize_into(
+                    &mut self.pending,
+                    &Record::<()>::DroppedTasks(dropped_tasks),
+                )
+                .map_err(io::Error::other)?;
+        }
+        let frame = CompactFrameRef {
+            frame,
+            names: &self.frame_names,
+        };
+        format::record_options()
+            .serialize_into(&mut self.pending, &Record::Frame(frame))
+            .map_err(io::Error::other)?;
+
+        if self.pending.len() >= PENDING_CAPACITY {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Look up the ids of a task's names, in the same order [`CompactTaskRef`] serializes them
+    ///
+    /// Also writes the task's args, which refer to the task by its position in that order
+    fn intern_names<T: WriteTask>(&mut self, task: &T) -> io::Result<()> {
+        let id = self.intern_name(task.name())?;
+        let index = self.frame_names.len() as u32;
+        self.frame_names.push(id);
+
+        if !task.args().is_empty() {
+            let args = self.intern_args(task.args())?;
+            format::record_options()
+                .serialize_into(&mut self.pending, &Record::<()>::Args(index, args))
+                .map_err(io::Error::other)?;
+        }
+
+        for subtask in task.subtasks() {
+            self.intern_names(subtask)?;
+        }
+        Ok(())
+    }
+
+    /// Look up the ids of args' names
+    fn intern_args<N: AsRef<str>>(
+        &mut self,
+        args: &[(N, ArgValue)],
+    ) -> io::Result<Vec<(u32, ArgValue)>> {
+        args.iter()
+            .map(|(name, value)| Ok((self.intern_name(name.as_ref())?, value.clone())))
+            .collect()
+    }
+
+    /// Look up a name's id, adding it to the string table if it's new
+    fn intern_name(&mut self, name: &str) -> io::Result<u32> {
+        if let Some(&id) = self.names.get(name) {
+            return Ok(id);
+        }
+        let id = self.names.len() as u32;
+        self.names.insert(name.into(), id);
+        format::record_options()
+            .serialize_into(&mut self.pending, &Record::<()>::Name(id, name.into()))
+            .map_err(io::Error::other)?;
+        Ok(id)
+    }
+
+    /// Compress and write the frames written so far.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let encoder = match &mut self.encoder {
+            Some(encoder) => encoder,
+            None => return Ok(()),
+        };
+
+        // Each batch starts at a new chunk, so the frames in it can be found by skipping from the start of that chunk
+        let chunk = encoder.get_ref().count;
+        self.frame_index.extend(
+            self.pending_frames
+                .drain(..)
+                .map(|skip| FrameOffset { chunk, skip }),
+        );
+        encoder.write_all(&self.pending)?;
+        encoder.flush()?;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Write any remaining frames and the frame index, and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush()?;
+        let mut writer = self
+            .encoder
+            .take()
+            .expect("WTF: ProfileWriter has already finished")
+            .into_inner()
+            .map_err(|err| err.into_error())?;
+
+        let index_start = writer.count;
+
+        let mut names = self.names.iter().collect::<Vec<_>>();
+        names.sort_unstable_by_key(|(_, &id)| id);
+        let mut table = Vec::new();
+        for (name, _) in names {
+            format::record_options()
+                .serialize_into(&mut table, name)
+                .map_err(io::Error::other)?;
+        }
+        write_split_chunks(&mut writer, format::INDEX_NAMES_CHUNK_TYPE, &table)?;
+
+        let mut metadata = Vec::new();
+        for pair in &self.metadata {
+            format::record_options()
+                .serialize_into(&mut metadata, pair)
+                .map_err(io::Error::other)?;
+        }
+        write_split_chunks(&mut writer, format::INDEX_METADATA_CHUNK_TYPE, &metadata)?;
+
+        for frames in self.frame_index.chunks(format::INDEX_CHUNK_FRAMES) {
+            let mut chunk = Vec::with_capacity(4 + frames.len() * 16);
+            chunk.extend(format::chunk_header(
+                format::INDEX_CHUNK_TYPE,
+                frames.len() * 16,
+            ));
+            for frame in frames {
+                chunk.extend(frame.chunk.to_le_bytes());
+                chunk.extend(frame.skip.to_le_bytes());
+            }
+            writer.write_all(&chunk)?;
+        }
+
+        let mut trailer = Vec::with_capacity(format::INDEX_TRAILER_LEN);
+        trailer.extend(format::chunk_header(
+            format::INDEX_TRAILER_CHUNK_TYPE,
+            format::INDEX_TRAILER_LEN - 4,
+        ));
+        trailer.extend(format::INDEX_TRAILER_MAGIC);
+        trailer.extend(index_start.to_le_bytes());
+        trailer.extend((self.frame_index.len() as u64).to_le_bytes());
+        writer.write_all(&trailer)?;
+
+        writer.inner.flush()?;
+        Ok(writer.inner)
+    }
+}
+
+impl<W: Write> Drop for ProfileWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Write `data` as chunks of type `chunk_type`, split to fit the maximum chunk length
+fn write_split_chunks<W: Write>(writer: &mut W, chunk_type: u8, data: &[u8]) -> io::Result<()> {
+    for chunk in data.chunks(format::INDEX_TABLE_CHUNK_LEN) {
+        writer.write_all(&format::chunk_header(chunk_type, chunk.len()))?;
+        writer.write_all(chunk)?;
+    }
+    Ok(())
+}
+
+/// Counts the bytes written, so that the frame index can refer to positions in the profile
+struct CountingWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A task that can be written to a profile
+pub(crate) trait WriteTask: Sized {
+    type ArgName: AsRef<str>;
+
+    fn name(&self) -> &str;
+    fn start(&self) -> Option<Duration>;
+    fn duration(&self) -> Duration;
+    fn thread(&self) -> u64;
+    fn args(&self) -> &[(Self::ArgName, ArgValue)];
+    fn subtasks(&self) -> &[Self];
+}
+
+impl WriteTask for TaskData {
+    type ArgName = Box<str>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn start(&self) -> Option<Duration> {
+        self.start
+    }
+
+    fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    fn thread(&self) -> u64 {
+        self.thread
+    }
+
+    fn args(&self) -> &[(Box<str>, ArgValue)] {
+        &self.args
+    }
+
+    fn subtasks(&self) -> &[Self] {
+        &self.subtasks
+    }
+}
+
+/// Serializes a frame as a [`CompactFrame`](format::CompactFrame)
+struct CompactFrameRef<'a, T: WriteTask> {
+    frame: &'a T,
+    /// The name id of every task in the frame, in the order they're serialized
+    names: &'a [u32],
+}
+
+impl<T: WriteTask> Serialize for CompactFrameRef<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut frame = serializer.serialize_struct("CompactFrame", 5)?;
+        frame.serialize_field("name", self.frame.name())?;
+        frame.serialize_field(
+            "start",
+            &self.frame.start().map(|start| start.as_nanos() as u64),
+        )?;
+        frame.serialize_field("duration", &(self.frame.duration().as_nanos() as u64))?;
+        frame.serialize_field("thread", &self.frame.thread())?;
+        frame.serialize_field(
+            "subtasks",
+            &CompactSubtasks {
+                parent: self.frame,
+                names: self.names,
+                next_name: &Cell::new(0),
+            },
+        )?;
+        frame.end()
+    }
+}
+
+/// Serializes a task as a [`CompactTask`](format::CompactTask)
+struct CompactTaskRef<'a, T: WriteTask> {
+    task: &'a T,
+    parent_start: Option<Duration>,
+    /// The name id of every task in the frame, in the order they're serialized
+    names: &'a [u32],
+    next_name: &'a Cell<usize>,
+}
+
+impl<T: WriteTask> Serialize for CompactTaskRef<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let name = self.names[self.next_name.get()];
+        self.next_name.set(self.next_name.get() + 1);
+
+        let mut task = serializer.serialize_struct("CompactTask", 5)?;
+        task.serialize_field("name", &name)?;
+        task.serialize_field(
+            "start",
+            &format::encode_start(self.task.start(), self.parent_start),
+        )?;
+        task.serialize_field("duration", &(self.task.duration().as_nanos() as u64))?;
+        task.serialize_field("thread", &self.task.thread())?;
+        task.serialize_field(
+            "subtasks",
+            &CompactSubtasks {
+                parent: self.task,
+                names: self.names,
+                next_name: self.next_name,
+            },
+        )?;
+        task.end()
+    }
+}
+
+struct CompactSubtasks<'a, T: WriteTask> {
+    parent: &'a T,
+    names: &'a [u32],
+    next_name: &'a Cell<usize>,
+}
+
+impl<T: WriteTask> Serialize for CompactSubtasks<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let subtasks = self.parent.subtasks();
+        let mut seq = serializer.serialize_seq(Some(subtasks.len()))?;
+        for subtask in subtasks {
+            seq.serialize_element(&CompactTaskRef {
+                task: subtask,
+                parent_start: self.parent.start(),
+                names: self.names,
+                next_name: self.next_name,
+            })?;
+        }
+        seq.end()
+    }
+}
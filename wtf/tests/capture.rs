@@ -0,0 +1,81 @@
+use std::fs::File;
+use wtf::{Profile, Profiler, ProfilerConfig};
+
+/// Read a capture's metadata and frame names
+fn read_capture(path: &std::path::Path) -> (Vec<(String, String)>, Vec<String>) {
+    let mut profile = Profile::open(File::open(path).unwrap()).unwrap();
+    let frames = profile
+        .frames_from(0)
+        .unwrap()
+        .map(|frame| frame.unwrap().name.into_string())
+        .collect();
+    (profile.metadata().to_vec(), frames)
+}
+
+fn get<'a>(metadata: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    metadata
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Each capture is saved to its own file, with a header describing it, and nothing is recorded between captures
+#[test]
+fn start_and_stop_captures() {
+    let output_dir = std::env::temp_dir().join(format!("wtf-capture-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("{label}-{capture}.wtf")
+            .label("menu")
+            .capture_on_start(false),
+    )
+    .unwrap();
+    assert!(Profiler::configure(ProfilerConfig::new()).is_err());
+    assert!(!Profiler::is_capturing());
+
+    let record_frame = |name: &'static str| {
+        let _frame = Profiler::new_frame();
+        let _task = Profiler::profile_task(name);
+    };
+    record_frame("not captured");
+
+    Profiler::set_metadata("level", "castle");
+    Profiler::start_capture().unwrap();
+    assert!(Profiler::is_capturing());
+    assert!(Profiler::start_capture().is_err());
+    record_frame("first");
+    Profiler::stop_capture();
+    assert!(!Profiler::is_capturing());
+    // Stopping again does nothing
+    Profiler::stop_capture();
+    record_frame("not captured");
+
+    assert!(Profiler::toggle_capture().unwrap());
+    Profiler::set_metadata("level", "dungeon");
+    record_frame("second");
+    record_frame("second");
+    assert!(!Profiler::toggle_capture().unwrap());
+    Profiler::end_profiling();
+    assert!(Profiler::start_capture().is_err());
+
+    let first = read_capture(&output_dir.join("menu-1.wtf"));
+    let second = read_capture(&output_dir.join("menu-2.wtf"));
+    let file_count = std::fs::read_dir(&output_dir).unwrap().count();
+    let _ = std::fs::remove_dir_all(&output_dir);
+    assert_eq!(file_count, 2);
+
+    assert_eq!(first.1, ["Frame #1"]);
+    assert_eq!(get(&first.0, "label"), Some("menu"));
+    assert_eq!(get(&first.0, "level"), Some("castle"));
+    assert_eq!(
+        get(&first.0, "wtf_version"),
+        Some(env!("CARGO_PKG_VERSION"))
+    );
+    assert!(get(&first.0, "program").unwrap().starts_with("capture"));
+    assert!(get(&first.0, "start_time").is_some());
+
+    // Metadata set during a capture replaces the value from its header
+    assert_eq!(second.1, ["Frame #1", "Frame #2"]);
+    assert_eq!(get(&second.0, "level"), Some("dungeon"));
+}
@@ -0,0 +1,165 @@
+//! Profiles and assertions shared by the tests
+
+// Each test only uses some of these
+#![allow(dead_code)]
+
+use serde::Serialize;
+use snap::write::FrameEncoder;
+use std::io::Write;
+use std::time::Duration;
+use wtf::{ArgValue, Mark, ProfileWriter, TaskData};
+
+pub fn task(name: &str, start_us: u64, duration_us: u64, thread: u64) -> TaskData {
+    TaskData {
+        name: name.into(),
+        start: Some(Duration::from_micros(start_us)),
+        duration: Duration::from_micros(duration_us),
+        thread,
+        ..TaskData::default()
+    }
+}
+
+/// A few frames using everything a profile can store: repeated names, nested tasks on several threads, args on tasks and
+/// frames, counters, marks, and dropped tasks
+pub fn sample_profile() -> Vec<TaskData> {
+    let mut first = task("Frame #1", 100, 16_000, 0);
+    first.counters = vec![("draw_calls".into(), 120.0), ("entities".into(), 4.5)].into();
+    first.marks = vec![Mark {
+        name: "level_loaded".into(),
+        time: Duration::from_micros(2_000),
+        thread: 1,
+        payload: Some("castle".into()),
+    }]
+    .into();
+    first.args = vec![("scene".into(), ArgValue::from("castle"))].into();
+    let mut physics = task("physics", 200, 5_000, 0);
+    physics.args = vec![
+        ("bodies".into(), ArgValue::Int(-3)),
+        ("step".into(), ArgValue::Float(0.5)),
+    ]
+    .into();
+    physics.subtasks = vec![
+        task("collisions", 300, 1_000, 0),
+        task("solve", 1_400, 2_000, 0),
+    ]
+    .into();
+    let mut load = task("load_texture \"stone\"", 150, 9_000, 1);
+    load.args = vec![("bytes".into(), ArgValue::Int(1 << 40))].into();
+    first.subtasks = vec![physics, load, task("render", 6_000, 9_000, 0)].into();
+
+    let mut second = task("Frame #2", 16_200, 20_000, 0);
+    second.dropped_tasks = 7;
+    second.counters = vec![("draw_calls".into(), 95.0)].into();
+    let mut physics = task("physics", 16_300, 4_000, 0);
+    physics.subtasks = vec![task("collisions", 16_400, 800, 0)].into();
+    second.subtasks = vec![physics, task("render", 20_400, 15_000, 0)].into();
+
+    // An empty frame
+    let third = task("Frame #3", 36_300, 10, 0);
+
+    vec![first, second, third]
+}
+
+/// Assert that two tasks and all their subtasks are the same
+pub fn assert_same_task(left: &TaskData, right: &TaskData) {
+    assert_eq!(left.name, right.name);
+    assert_eq!(left.start, right.start, "{}", left.name);
+    assert_eq!(left.duration, right.duration, "{}", left.name);
+    assert_eq!(left.thread, right.thread, "{}", left.name);
+    assert_eq!(left.dropped_tasks, right.dropped_tasks, "{}", left.name);
+    assert_eq!(left.counters, right.counters, "{}", left.name);
+    assert_eq!(left.args, right.args, "{}", left.name);
+    assert_eq!(left.marks.len(), right.marks.len(), "{}", left.name);
+    for (left, right) in left.marks.iter().zip(right.marks.iter()) {
+        assert_eq!(left.name, right.name);
+        assert_eq!(left.time, right.time, "{}", left.name);
+        assert_eq!(left.thread, right.thread, "{}", left.name);
+        assert_eq!(left.payload, right.payload, "{}", left.name);
+    }
+    assert_eq!(left.subtasks.len(), right.subtasks.len(), "{}", left.name);
+    for (left, right) in left.subtasks.iter().zip(right.subtasks.iter()) {
+        assert_same_task(left, right);
+    }
+}
+
+/// Assert that two profiles have the same frames
+pub fn assert_same_frames(left: &[TaskData], right: &[TaskData]) {
+    assert_eq!(left.len(), right.len());
+    for (left, right) in left.iter().zip(right) {
+        assert_same_task(left, right);
+    }
+}
+
+/// Write a profile with a [`ProfileWriter`], flushing after every `frames_per_flush` frames so that frames are spread over
+/// several chunks
+pub fn write_profile(frames: &[TaskData], frames_per_flush: usize) -> Vec<u8> {
+    let mut writer = ProfileWriter::new(Vec::new()).unwrap();
+    for (i, frame) in frames.iter().enumerate() {
+        writer.write_frame(frame).unwrap();
+        if (i + 1) % frames_per_flush == 0 {
+            writer.flush().unwrap();
+        }
+    }
+    writer.finish().unwrap()
+}
+
+/// A task as stored in version 0 profiles
+#[derive(Serialize)]
+struct TaskV0<'a> {
+    name: &'a str,
+    duration: Duration,
+    subtasks: Vec<TaskV0<'a>>,
+}
+
+impl<'a> From<&'a TaskData> for TaskV0<'a> {
+    fn from(task: &'a TaskData) -> Self {
+        Self {
+            name: &task.name,
+            duration: task.duration,
+            subtasks: task.subtasks.iter().map(Self::from).collect(),
+        }
+    }
+}
+
+/// Write a version 0 profile, which has no header: a snappy frame stream of bincode frames with only names, durations,
+/// and subtasks
+pub fn write_v0(frames: &[TaskData]) -> Vec<u8> {
+    let mut encoder = FrameEncoder::new(Vec::new());
+    for frame in frames {
+        bincode::serialize_into(&mut encoder, &TaskV0::from(frame)).unwrap();
+    }
+    encoder.into_inner().unwrap()
+}
+
+/// Write a version 1 profile: a header, then a snappy frame stream of bincode records with fixed size integers, where
+/// frames are whole [`TaskData`]s. Frames' dropped tasks are written, but not their counters, marks, or args, which
+/// version 1 didn't have
+pub fn write_v1(frames: &[TaskData], metadata: &[(String, String)]) -> Vec<u8> {
+    let mut profile = b"WTFPROF\0".to_vec();
+    profile.extend(1u32.to_le_bytes());
+    let mut encoder = FrameEncoder::new(profile);
+    bincode::serialize_into(&mut encoder, &metadata).unwrap();
+    for frame in frames {
+        // The variant indexes of `Record::DroppedTasks` and `Record::Frame`
+        if frame.dropped_tasks > 0 {
+            bincode::serialize_into(&mut encoder, &(1u32, frame.dropped_tasks)).unwrap();
+        }
+        bincode::serialize_into(&mut encoder, &(0u32, frame)).unwrap();
+    }
+    encoder.flush().unwrap();
+    encoder.into_inner().unwrap()
+}
+
+/// Remove what a version 1 profile can't store from frames
+pub fn without_v2_data(frames: &[TaskData]) -> Vec<TaskData> {
+    fn strip(task: &TaskData) -> TaskData {
+        TaskData {
+            counters: Box::default(),
+            marks: Box::default(),
+            args: Box::default(),
+            subtasks: task.subtasks.iter().map(strip).collect(),
+            ..task.clone()
+        }
+    }
+    frames.iter().map(strip).collect()
+}
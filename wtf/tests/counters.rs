@@ -0,0 +1,60 @@
+use std::thread;
+use wtf::{Profiler, ProfilerConfig};
+
+/// Counters recorded on any thread belong to the frame in progress, or the next frame if there isn't one, and only the last
+/// value recorded during a frame is kept
+#[test]
+fn counters() {
+    let output_dir = std::env::temp_dir().join(format!("wtf-counters-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("counters.wtf"),
+    )
+    .unwrap();
+
+    {
+        let _frame = Profiler::new_frame();
+        Profiler::counter("entities", 1.0);
+        thread::spawn(|| Profiler::counter("draw_calls", 5.0))
+            .join()
+            .unwrap();
+        Profiler::counter("entities", 2.0);
+    }
+    Profiler::counter("entities", 3.0);
+    {
+        let _frame = Profiler::new_frame();
+    }
+    {
+        let _frame = Profiler::new_frame();
+    }
+    Profiler::end_profiling();
+
+    let frames =
+        wtf::read_profile_data(std::fs::File::open(output_dir.join("counters.wtf")).unwrap())
+            .unwrap();
+    let _ = std::fs::remove_dir_all(&output_dir);
+    let counters = frames
+        .iter()
+        .map(|frame| {
+            let mut counters = frame
+                .counters
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect::<Vec<_>>();
+            counters.sort_by(|a, b| a.0.cmp(&b.0));
+            counters
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        counters,
+        [
+            vec![
+                ("draw_calls".to_string(), 5.0),
+                ("entities".to_string(), 2.0)
+            ],
+            vec![("entities".to_string(), 3.0)],
+            vec![],
+        ]
+    );
+}
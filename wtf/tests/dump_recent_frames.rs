@@ -0,0 +1,44 @@
+use std::fs::File;
+use wtf::{KeepRecent, Profiler, ProfilerConfig};
+
+fn frame_names(path: &std::path::Path) -> Vec<String> {
+    wtf::read_profile_data(File::open(path).unwrap())
+        .unwrap()
+        .iter()
+        .map(|frame| frame.name.to_string())
+        .collect()
+}
+
+/// Dumping saves the last few frames to a new file each time
+#[test]
+fn dump_recent_frames() {
+    let output_dir =
+        std::env::temp_dir().join(format!("wtf-dump-frames-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("recent-{dump}.wtf")
+            .keep_recent(KeepRecent::Frames(3)),
+    )
+    .unwrap();
+
+    let record_frame = || {
+        let _frame = Profiler::new_frame();
+        let _task = Profiler::profile_task("update");
+    };
+    for _ in 0..5 {
+        record_frame();
+    }
+    Profiler::dump_recent().unwrap();
+    record_frame();
+    Profiler::dump_recent().unwrap();
+    Profiler::end_profiling();
+
+    let first = frame_names(&output_dir.join("recent-1.wtf"));
+    let second = frame_names(&output_dir.join("recent-2.wtf"));
+    let file_count = std::fs::read_dir(&output_dir).unwrap().count();
+    let _ = std::fs::remove_dir_all(&output_dir);
+    assert_eq!(file_count, 2);
+    assert_eq!(first, ["Frame #3", "Frame #4", "Frame #5"]);
+    assert_eq!(second, ["Frame #4", "Frame #5", "Frame #6"]);
+}
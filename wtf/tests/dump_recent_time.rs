@@ -0,0 +1,47 @@
+use std::fs::File;
+use std::thread;
+use std::time::Duration;
+use wtf::{KeepRecent, Profiler, ProfilerConfig};
+
+fn frame_names(path: &std::path::Path) -> Vec<String> {
+    wtf::read_profile_data(File::open(path).unwrap())
+        .unwrap()
+        .iter()
+        .map(|frame| frame.name.to_string())
+        .collect()
+}
+
+/// Dumping saves the frames that started within the kept time of the end of the last frame, and always the last frame
+#[test]
+fn dump_recent_time() {
+    let output_dir =
+        std::env::temp_dir().join(format!("wtf-dump-time-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("recent-{dump}.wtf")
+            .keep_recent(KeepRecent::Time(Duration::from_millis(100))),
+    )
+    .unwrap();
+
+    let record_frame = |duration: u64| {
+        let _frame = Profiler::new_frame();
+        let _task = Profiler::profile_task("update");
+        thread::sleep(Duration::from_millis(duration));
+    };
+    // Longer than the kept time, but it's the only frame
+    record_frame(150);
+    Profiler::dump_recent().unwrap();
+    record_frame(10);
+    record_frame(10);
+    Profiler::dump_recent().unwrap();
+    Profiler::end_profiling();
+
+    let first = frame_names(&output_dir.join("recent-1.wtf"));
+    let second = frame_names(&output_dir.join("recent-2.wtf"));
+    let file_count = std::fs::read_dir(&output_dir).unwrap().count();
+    let _ = std::fs::remove_dir_all(&output_dir);
+    assert_eq!(file_count, 2);
+    assert_eq!(first, ["Frame #1"]);
+    assert_eq!(second, ["Frame #2", "Frame #3"]);
+}
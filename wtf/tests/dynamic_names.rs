@@ -0,0 +1,60 @@
+use std::fs::File;
+use wtf::{Profile, Profiler, ProfilerConfig, TaskData};
+
+/// The names of a frame's tasks, with their subtasks' names after them
+fn task_names(frame: &TaskData) -> Vec<String> {
+    frame
+        .subtasks
+        .iter()
+        .flat_map(|task| std::iter::once(task).chain(task.subtasks.iter()))
+        .map(|task| task.name.to_string())
+        .collect()
+}
+
+/// Names built at runtime, and names repeated across frames, are read back from a capture as they were recorded
+#[test]
+fn dynamic_names() {
+    let output_dir =
+        std::env::temp_dir().join(format!("wtf-dynamic-names-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("dynamic-names.wtf"),
+    )
+    .unwrap();
+
+    for frame in 0..4 {
+        let _frame = Profiler::new_frame();
+        {
+            let _level = Profiler::profile_task_dyn(format!("load_level {}", frame % 2));
+            let _chunk = Profiler::profile_task_dyn(format!("load_chunk {}", frame));
+        }
+        {
+            let _update = Profiler::profile_task("update");
+        }
+        // The same name as a static name
+        let _update = Profiler::profile_task_dyn("update");
+    }
+    Profiler::end_profiling();
+
+    let path = output_dir.join("dynamic-names.wtf");
+    let frames = wtf::read_profile_data(File::open(&path).unwrap()).unwrap();
+    let mut profile = Profile::open(File::open(&path).unwrap()).unwrap();
+    let last = profile.frame(3).unwrap().unwrap();
+    let _ = std::fs::remove_dir_all(&output_dir);
+
+    assert_eq!(frames.len(), 4);
+    for (i, frame) in frames.iter().enumerate() {
+        assert_eq!(
+            task_names(frame),
+            [
+                format!("load_level {}", i % 2),
+                format!("load_chunk {}", i),
+                "update".to_string(),
+                "update".to_string(),
+            ]
+        );
+    }
+    // Reading a frame on its own finds the names first written in earlier frames
+    assert_eq!(task_names(&last), task_names(&frames[3]));
+}
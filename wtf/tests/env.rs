@@ -0,0 +1,33 @@
+use std::env;
+use std::fs::File;
+use wtf::{Profile, Profiler};
+
+/// Without a call to `Profiler::configure`, profiling is configured by environment variables
+#[test]
+fn configure_from_env() {
+    let output_dir = env::temp_dir().join(format!("wtf-env-test-{}", std::process::id()));
+    env::set_var("WTF_OUTPUT_DIR", &output_dir);
+    env::set_var("WTF_OUTPUT_FILE", "{label}-{pid}-{capture}.wtf");
+    env::set_var("WTF_LABEL", "from env");
+    env::set_var("WTF_CAPTURE", "false");
+    // Invalid values are ignored
+    env::set_var("WTF_OVERFLOW_POLICY", "sometimes");
+    env::set_var("WTF_FLUSH_INTERVAL_MS", "soon");
+
+    assert!(!Profiler::is_capturing());
+    Profiler::start_capture().unwrap();
+    {
+        let _frame = Profiler::new_frame();
+        let _task = Profiler::profile_task("update");
+    }
+    Profiler::end_profiling();
+
+    let path = output_dir.join(format!("from env-{}-1.wtf", std::process::id()));
+    let profile = File::open(&path).map(Profile::open);
+    let _ = std::fs::remove_dir_all(&output_dir);
+    let profile = profile.unwrap().unwrap();
+    assert_eq!(profile.frame_count(), 1);
+    assert!(profile
+        .metadata()
+        .contains(&("label".to_string(), "from env".to_string())));
+}
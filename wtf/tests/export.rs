@@ -0,0 +1,107 @@
+mod common;
+
+use serde_json::{json, Value};
+use wtf::ArgValue;
+
+/// Export a profile, and parse the JSON written
+fn export(frames: Vec<wtf::TaskData>) -> Vec<Value> {
+    let mut json = Vec::new();
+    wtf::export::chrome_trace(&frames.into_boxed_slice(), &mut json).unwrap();
+    let trace: Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(trace["displayTimeUnit"], "ms");
+    trace["traceEvents"].as_array().unwrap().clone()
+}
+
+fn find<'a>(events: &'a [Value], name: &str, phase: &str) -> Vec<&'a Value> {
+    events
+        .iter()
+        .filter(|event| event["name"] == name && event["ph"] == phase)
+        .collect()
+}
+
+#[test]
+fn chrome_trace() {
+    let mut frames = common::sample_profile();
+    frames[1].subtasks[1].args = vec![
+        ("visible".into(), ArgValue::Bool(true)),
+        ("scale".into(), ArgValue::Float(f64::INFINITY)),
+    ]
+    .into();
+    let events = export(frames);
+
+    // Each thread gets a named track, and frames get one after them
+    let track_names = find(&events, "thread_name", "M")
+        .into_iter()
+        .map(|event| {
+            (
+                event["tid"].as_u64().unwrap(),
+                event["args"]["name"].clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        track_names,
+        [
+            (2, json!("Frames")),
+            (0, json!("Thread 0")),
+            (1, json!("Thread 1"))
+        ]
+    );
+
+    let frame = find(&events, "Frame #1", "X")[0];
+    assert_eq!(frame["tid"], 2);
+    assert_eq!(frame["ts"], 100.0);
+    assert_eq!(frame["dur"], 16_000.0);
+    assert_eq!(frame["args"], json!({"draw_calls": 120, "entities": 4.5}));
+    let frame = find(&events, "Frame #2", "X")[0];
+    assert_eq!(frame["args"], json!({"draw_calls": 95, "dropped_tasks": 7}));
+
+    let physics = find(&events, "physics", "X");
+    assert_eq!(physics.len(), 2);
+    assert_eq!(physics[0]["tid"], 0);
+    assert_eq!(physics[0]["ts"], 200.0);
+    assert_eq!(physics[0]["args"], json!({"bodies": -3, "step": 0.5}));
+    let load = find(&events, "load_texture \"stone\"", "X")[0];
+    assert_eq!(load["tid"], 1);
+    assert_eq!(load["args"], json!({"bytes": 1i64 << 40}));
+    let render = find(&events, "render", "X");
+    assert_eq!(render[1]["args"], json!({"visible": true, "scale": "inf"}));
+
+    // Marks are instant events on their thread's track
+    let marks = find(&events, "level_loaded", "i");
+    assert_eq!(marks.len(), 1);
+    assert_eq!(
+        *marks[0],
+        json!({
+            "name": "level_loaded",
+            "ph": "i",
+            "pid": 1,
+            "tid": 1,
+            "ts": 2_000.0,
+            "s": "t",
+            "args": {"payload": "castle"},
+        })
+    );
+}
+
+/// Frames and tasks without a start are placed after the previous one
+#[test]
+fn chrome_trace_without_starts() {
+    let mut frames = common::without_v2_data(&common::sample_profile());
+    fn clear_starts(task: &mut wtf::TaskData) {
+        task.start = None;
+        for task in task.subtasks.iter_mut() {
+            clear_starts(task);
+        }
+    }
+    frames.iter_mut().for_each(clear_starts);
+    let events = export(frames);
+
+    let starts = ["Frame #1", "Frame #2", "Frame #3"]
+        .iter()
+        .map(|name| find(&events, name, "X")[0]["ts"].as_f64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(starts, [0.0, 16_000.0, 36_000.0]);
+    let solve = find(&events, "solve", "X")[0];
+    assert_eq!(solve["ts"], 1_000.0);
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use std::io::Cursor;
+use std::time::Duration;
+use wtf::{ArgValue, Mark, Profile, TaskData};
+
+/// Frames with the starts, threads, durations, and names that are easiest to get wrong when storing tasks compactly
+fn unusual_frames() -> Vec<TaskData> {
+    // Tasks without a start, or whose parent has none, store their start as an absolute time rather than one relative
+    // to their parent
+    let mut without_start = common::task("Frame without a start", 0, 500, 0);
+    without_start.start = None;
+    let mut unknown = common::task("unknown start", 0, 100, 0);
+    unknown.start = None;
+    unknown.subtasks = vec![common::task("after unknown start", 40, 20, 0)].into();
+    without_start.subtasks = vec![common::task("absolute", 30, 100, 0), unknown].into();
+
+    // Tasks on other threads can start before their parent
+    let mut early = common::task("Frame with an early task", 10_000, 1_000, 0);
+    let mut worker = common::task("worker", 9_000, 3_000, u64::MAX);
+    worker.subtasks = vec![
+        common::task("before the frame", 9_500, 10, u64::MAX),
+        common::task("Ω ünïcödé 🦀", 10_500, 10, u64::MAX),
+    ]
+    .into();
+    early.subtasks = vec![worker].into();
+    early.marks = vec![Mark {
+        name: "before the frame".into(),
+        time: Duration::from_micros(9_000),
+        thread: u64::MAX,
+        payload: None,
+    }]
+    .into();
+
+    // Long captures have large starts and durations
+    let mut long = common::task("Ω ünïcödé 🦀", 0, 0, 1);
+    long.start = Some(Duration::from_secs(60 * 60 * 24 * 30));
+    long.duration = Duration::from_nanos(u64::MAX);
+    let mut task = common::task("", 0, 0, 1);
+    task.start = Some(Duration::from_secs(60 * 60 * 24 * 30) + Duration::from_nanos(1));
+    task.duration = Duration::from_secs(60 * 60);
+    task.args = vec![
+        ("".into(), ArgValue::Int(i64::MIN)),
+        ("Ω ünïcödé 🦀".into(), ArgValue::Float(f64::MAX)),
+    ]
+    .into();
+    long.subtasks = vec![task].into();
+
+    vec![without_start, early, long]
+}
+
+#[test]
+fn round_trip_unusual_frames() {
+    let frames = unusual_frames();
+    for frames_per_flush in [1, frames.len()] {
+        let profile = common::write_profile(&frames, frames_per_flush);
+        common::assert_same_frames(&wtf::read_profile_data(&profile[..]).unwrap(), &frames);
+
+        // The string table is also read from the index
+        let mut profile = Profile::open(Cursor::new(profile)).unwrap();
+        for (n, frame) in frames.iter().enumerate().rev() {
+            common::assert_same_task(&profile.frame(n).unwrap().unwrap(), frame);
+        }
+    }
+}
+
+/// Names are only stored the first time they're used, so frames that reuse names from many earlier frames must still
+/// have them
+#[test]
+fn round_trip_reused_names() {
+    let frames = (0..1000)
+        .map(|i| {
+            let mut frame = common::task("Frame", i * 10, 10, 0);
+            frame.subtasks = (0..=i % 50)
+                .map(|j| common::task(&format!("task {}", j), i * 10, 1, 0))
+                .collect();
+            frame
+        })
+        .collect::<Vec<_>>();
+    let profile = common::write_profile(&frames, 64);
+    common::assert_same_frames(&wtf::read_profile_data(&profile[..]).unwrap(), &frames);
+    let mut profile = Profile::open(Cursor::new(profile)).unwrap();
+    common::assert_same_task(&profile.frame(999).unwrap().unwrap(), &frames[999]);
+}
+
+/// Version 1 stored tasks without a string table or relative starts, and has no index
+#[test]
+fn version_1_still_decodes() {
+    let frames = common::without_v2_data(&unusual_frames());
+    let profile = common::write_v1(&frames, &[("program".into(), "game".into())]);
+    common::assert_same_frames(&wtf::read_profile_data(&profile[..]).unwrap(), &frames);
+
+    let mut profile = Profile::open(Cursor::new(profile)).unwrap();
+    assert_eq!(profile.frame_count(), frames.len());
+    assert_eq!(
+        profile.metadata(),
+        &[("program".to_string(), "game".to_string())]
+    );
+    common::assert_same_task(&profile.frame(1).unwrap().unwrap(), &frames[1]);
+}
@@ -0,0 +1,58 @@
+use std::thread;
+use std::time::Duration;
+use wtf::{Profile, Profiler, ProfilerConfig};
+
+/// Each hitch is saved to its own file with the frames around it, even when the file name has no `{dump}` placeholder
+#[test]
+fn two_hitches() {
+    let output_dir = std::env::temp_dir().join(format!("wtf-hitches-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("hitch.wtf")
+            .capture_hitches(Duration::from_millis(20), 1, 1),
+    )
+    .unwrap();
+
+    // Frames 3 and 7 are slow
+    for frame in 1..=9 {
+        let _frame = Profiler::new_frame();
+        let _task = Profiler::profile_task("update");
+        if frame == 3 || frame == 7 {
+            thread::sleep(Duration::from_millis(30));
+        }
+    }
+    Profiler::end_profiling();
+
+    let mut files = std::fs::read_dir(&output_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    files.sort();
+    let dumps = files
+        .iter()
+        .map(|path| {
+            let mut profile = Profile::open(std::fs::File::open(path).unwrap()).unwrap();
+            let slow_frame = profile
+                .metadata()
+                .iter()
+                .find(|(key, _)| key == "slow_frame")
+                .map(|(_, value)| value.clone());
+            let frames = profile
+                .frames_from(0)
+                .unwrap()
+                .map(|frame| frame.unwrap().name.into_string())
+                .collect::<Vec<_>>();
+            (path.file_name().unwrap().to_owned(), slow_frame, frames)
+        })
+        .collect::<Vec<_>>();
+    let _ = std::fs::remove_dir_all(&output_dir);
+
+    assert_eq!(dumps.len(), 2);
+    assert_eq!(dumps[0].0, "hitch-1.wtf");
+    assert_eq!(dumps[0].1.as_deref(), Some("Frame #3"));
+    assert_eq!(dumps[0].2, ["Frame #2", "Frame #3", "Frame #4"]);
+    assert_eq!(dumps[1].0, "hitch-2.wtf");
+    assert_eq!(dumps[1].1.as_deref(), Some("Frame #7"));
+    assert_eq!(dumps[1].2, ["Frame #6", "Frame #7", "Frame #8"]);
+}
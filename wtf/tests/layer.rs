@@ -0,0 +1,107 @@
+use std::time::Duration;
+use tracing::field;
+use tracing_subscriber::layer::SubscriberExt;
+use wtf::{ArgValue, Profiler, ProfilerConfig, ProfilerLayer};
+
+/// Spans become frames and tasks, and their fields become args, and spans entered on several threads at once become
+/// a task on each thread
+#[test]
+fn spans_become_tasks() {
+    let output_dir = std::env::temp_dir().join(format!("wtf-layer-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("layer.wtf"),
+    )
+    .unwrap();
+
+    let subscriber = tracing_subscriber::registry().with(ProfilerLayer::new().frame_span("frame"));
+    // The default for every thread, so spans entered on other threads are recorded too
+    tracing::subscriber::set_global_default(subscriber).unwrap();
+    for frame in 0..2u64 {
+        let _frame = tracing::info_span!("frame").entered();
+        let update = tracing::info_span!(
+            "update",
+            frame,
+            delta = -1i64,
+            time = 0.5,
+            paused = false,
+            level = "castle",
+            size = ?(1, 2),
+            late = field::Empty,
+        );
+        let _update = update.enter();
+        // Fields recorded after entering apply to the task in progress
+        update.record("late", true);
+        {
+            let _physics = tracing::info_span!("physics").entered();
+        }
+        let _render = tracing::info_span!("render").entered();
+    }
+    {
+        let _frame = tracing::info_span!("frame").entered();
+        let shared = tracing::info_span!("shared");
+        let (entered_sender, entered_reciever) = std::sync::mpsc::channel();
+        let first = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                let _shared = shared.enter();
+                entered_sender.send(()).unwrap();
+                std::thread::sleep(Duration::from_millis(20));
+            })
+        };
+        // Entered while the first thread is still inside the span, and exited after it leaves
+        let second = std::thread::spawn(move || {
+            entered_reciever.recv().unwrap();
+            let _shared = shared.enter();
+            std::thread::sleep(Duration::from_millis(60));
+        });
+        first.join().unwrap();
+        second.join().unwrap();
+    }
+    Profiler::end_profiling();
+
+    let frames =
+        wtf::read_profile_data(std::fs::File::open(output_dir.join("layer.wtf")).unwrap()).unwrap();
+    let _ = std::fs::remove_dir_all(&output_dir);
+    assert_eq!(frames.len(), 3);
+    for (i, frame) in frames[..2].iter().enumerate() {
+        assert_eq!(&*frame.name, format!("Frame #{}", i + 1));
+        assert_eq!(frame.subtasks.len(), 1);
+        let update = &frame.subtasks[0];
+        assert_eq!(&*update.name, "update");
+        assert_eq!(
+            &*update.args,
+            &[
+                ("frame".into(), ArgValue::Int(i as i64)),
+                ("delta".into(), ArgValue::Int(-1)),
+                ("time".into(), ArgValue::Float(0.5)),
+                ("paused".into(), ArgValue::Bool(false)),
+                ("level".into(), ArgValue::from("castle")),
+                ("size".into(), ArgValue::from("(1, 2)")),
+                ("late".into(), ArgValue::Bool(true)),
+            ]
+        );
+        let names = update
+            .subtasks
+            .iter()
+            .map(|task| &*task.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["physics", "render"]);
+        assert!(update.subtasks.iter().all(|task| task.args.is_empty()));
+    }
+
+    // Each thread's task ends when that thread exits the span
+    let mut shared = frames[2].subtasks.to_vec();
+    shared.sort_by_key(|task| task.start);
+    assert_eq!(shared.len(), 2);
+    assert!(shared.iter().all(|task| &*task.name == "shared"));
+    assert_ne!(shared[0].thread, shared[1].thread);
+    assert!(shared[0].duration >= Duration::from_millis(20));
+    assert!(shared[0].duration < Duration::from_millis(60));
+    assert!(shared[1].duration >= Duration::from_millis(60));
+    assert!(
+        shared[0].start.unwrap() + shared[0].duration
+            < shared[1].start.unwrap() + shared[1].duration
+    );
+}
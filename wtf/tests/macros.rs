@@ -0,0 +1,75 @@
+use wtf::{Profiler, ProfilerConfig};
+
+struct Player;
+
+impl Player {
+    #[wtf::profile]
+    fn update() {}
+}
+
+struct Enemy;
+
+impl Enemy {
+    #[wtf::profile]
+    fn update() {}
+}
+
+mod physics {
+    #[wtf::profile]
+    pub fn step() {}
+
+    #[wtf::profile("collisions")]
+    pub fn collide() {}
+}
+
+/// Tasks from the macros are named after the function or scope they profile, and methods with the same name on different
+/// types get different names
+#[test]
+fn task_names() {
+    let output_dir = std::env::temp_dir().join(format!("wtf-macros-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("macros.wtf"),
+    )
+    .unwrap();
+
+    let line = {
+        let _frame = Profiler::new_frame();
+        Player::update();
+        Enemy::update();
+        physics::step();
+        physics::collide();
+        {
+            wtf::profile_scope!("render");
+        }
+        {
+            wtf::profile_scope!("load_level {}", 3);
+        }
+        wtf::profile_scope!();
+        line!() - 1
+    };
+    Profiler::end_profiling();
+
+    let frames =
+        wtf::read_profile_data(std::fs::File::open(output_dir.join("macros.wtf")).unwrap())
+            .unwrap();
+    let _ = std::fs::remove_dir_all(&output_dir);
+    let names = frames[0]
+        .subtasks
+        .iter()
+        .map(|task| task.name.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "macros::Player::update".to_string(),
+            "macros::Enemy::update".to_string(),
+            "macros::physics::step".to_string(),
+            "collisions".to_string(),
+            "render".to_string(),
+            "load_level 3".to_string(),
+            format!("macros:{}", line),
+        ]
+    );
+}
@@ -0,0 +1,74 @@
+use std::thread;
+use wtf::{Profiler, ProfilerConfig};
+
+/// Marks recorded on any thread belong to the frame in progress, or the next frame if there isn't one, with the thread and
+/// time they were recorded at
+#[test]
+fn marks() {
+    let output_dir = std::env::temp_dir().join(format!("wtf-marks-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("marks.wtf"),
+    )
+    .unwrap();
+
+    {
+        let _frame = Profiler::new_frame();
+        Profiler::mark("input");
+        thread::spawn(|| {
+            let _load = Profiler::profile_task("load_level");
+            Profiler::mark_with_payload("level_loaded", "castle");
+        })
+        .join()
+        .unwrap();
+        Profiler::mark("render");
+    }
+    Profiler::mark("between");
+    {
+        let _frame = Profiler::new_frame();
+    }
+    Profiler::end_profiling();
+
+    let frames =
+        wtf::read_profile_data(std::fs::File::open(output_dir.join("marks.wtf")).unwrap()).unwrap();
+    let _ = std::fs::remove_dir_all(&output_dir);
+    assert_eq!(frames.len(), 2);
+
+    let first = &frames[0];
+    let mut marks = first.marks.to_vec();
+    marks.sort_by_key(|mark| mark.time);
+    let names = marks
+        .iter()
+        .map(|mark| (&*mark.name, mark.payload.as_deref()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            ("input", None),
+            ("level_loaded", Some("castle")),
+            ("render", None)
+        ]
+    );
+    let frame_start = first.start.unwrap();
+    assert!(marks
+        .iter()
+        .all(|mark| mark.time >= frame_start && mark.time <= frame_start + first.duration));
+    assert_eq!(marks[0].thread, first.thread);
+    assert_eq!(marks[2].thread, first.thread);
+
+    // The worker's mark is on its thread, during its task
+    let load = &first.subtasks[0];
+    assert_eq!(&*load.name, "load_level");
+    assert_ne!(load.thread, first.thread);
+    assert_eq!(marks[1].thread, load.thread);
+    assert!(marks[1].time >= load.start.unwrap());
+    assert!(marks[1].time <= load.start.unwrap() + load.duration);
+
+    let second = &frames[1];
+    assert_eq!(second.marks.len(), 1);
+    assert_eq!(&*second.marks[0].name, "between");
+    assert_eq!(second.marks[0].thread, second.thread);
+    assert!(second.marks[0].time >= frame_start + first.duration);
+    assert!(second.marks[0].time <= second.start.unwrap());
+}
@@ -0,0 +1,46 @@
+use wtf::{OverflowPolicy, Profiler, ProfilerConfig};
+
+/// Once a frame holds as many tasks as allowed, its new tasks are dropped, along with the tasks started inside them, and
+/// the frame records how many were lost
+#[test]
+fn drop_events() {
+    let output_dir =
+        std::env::temp_dir().join(format!("wtf-drop-events-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("drop-events.wtf")
+            // Up to 10 tasks are held for the frame in progress
+            .max_buffered_events(20)
+            .overflow_policy(OverflowPolicy::DropEvents),
+    )
+    .unwrap();
+
+    for frame in 0..3 {
+        let _frame = Profiler::new_frame();
+        let tasks = if frame == 1 { 12 } else { 4 };
+        for _ in 0..tasks {
+            let _task = Profiler::profile_task("update");
+        }
+        if frame == 1 {
+            let _parent = Profiler::profile_task("parent");
+            let _child = Profiler::profile_task("child");
+        }
+    }
+    Profiler::end_profiling();
+    assert_eq!(Profiler::dropped_tasks(), 4);
+
+    let frames =
+        wtf::read_profile_data(std::fs::File::open(output_dir.join("drop-events.wtf")).unwrap())
+            .unwrap();
+    let _ = std::fs::remove_dir_all(&output_dir);
+    let kept = frames
+        .iter()
+        .map(|frame| (frame.subtasks.len(), frame.dropped_tasks))
+        .collect::<Vec<_>>();
+    assert_eq!(kept, [(4, 0), (10, 4), (4, 0)]);
+    assert!(frames[1]
+        .subtasks
+        .iter()
+        .all(|task| &*task.name == "update"));
+}
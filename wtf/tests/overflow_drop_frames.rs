@@ -0,0 +1,42 @@
+use wtf::{OverflowPolicy, Profiler, ProfilerConfig};
+
+/// Once a frame holds as many tasks as allowed, its new tasks are dropped, and so are the tasks it kept, so that it
+/// doesn't appear to be faster than it was
+#[test]
+fn drop_frames() {
+    let output_dir =
+        std::env::temp_dir().join(format!("wtf-drop-frames-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("drop-frames.wtf")
+            // Up to 10 tasks are held for the frame in progress
+            .max_buffered_events(20)
+            .overflow_policy(OverflowPolicy::DropFrames),
+    )
+    .unwrap();
+
+    for frame in 0..3 {
+        let _frame = Profiler::new_frame();
+        let tasks = if frame == 1 { 12 } else { 4 };
+        for _ in 0..tasks {
+            let _task = Profiler::profile_task("update");
+        }
+        if frame == 1 {
+            let _parent = Profiler::profile_task("parent");
+            let _child = Profiler::profile_task("child");
+        }
+    }
+    Profiler::end_profiling();
+    assert_eq!(Profiler::dropped_tasks(), 14);
+
+    let frames =
+        wtf::read_profile_data(std::fs::File::open(output_dir.join("drop-frames.wtf")).unwrap())
+            .unwrap();
+    let _ = std::fs::remove_dir_all(&output_dir);
+    let kept = frames
+        .iter()
+        .map(|frame| (frame.subtasks.len(), frame.dropped_tasks))
+        .collect::<Vec<_>>();
+    assert_eq!(kept, [(4, 0), (0, 14), (4, 0)]);
+}
@@ -0,0 +1,59 @@
+//! Runs without the test harness, as the hook only ends profiling when the main thread panics
+
+use std::fs::File;
+use std::panic;
+use std::thread;
+use std::time::Duration;
+use wtf::{Profile, Profiler, ProfilerConfig};
+
+fn main() {
+    let output_dir = std::env::temp_dir().join(format!("wtf-panic-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("panic.wtf"),
+    )
+    .unwrap();
+    // Don't print the expected panics
+    panic::set_hook(Box::new(|_| {}));
+    Profiler::install_panic_hook();
+
+    for _ in 0..3 {
+        let _frame = Profiler::new_frame();
+        let _task = Profiler::profile_task("update");
+    }
+
+    // Other threads may recover from panicking, so profiling continues
+    assert!(thread::spawn(|| panic!("worker failed")).join().is_err());
+    assert!(Profiler::is_capturing());
+    {
+        let _frame = Profiler::new_frame();
+        let _task = Profiler::profile_task("update");
+    }
+
+    // The profile is saved with the frames before the panic
+    let result = panic::catch_unwind(|| {
+        let _frame = Profiler::new_frame();
+        let _task = Profiler::profile_task("crash");
+        panic!("game crashed");
+    });
+    assert!(result.is_err());
+    assert!(!Profiler::is_capturing());
+
+    // Profiling was already ended by the panic, so ending it again does nothing, rather than hanging or panicking
+    thread::spawn(|| {
+        thread::sleep(Duration::from_secs(10));
+        eprintln!("Profiler::end_profiling() hung after the panic hook ended profiling");
+        std::process::exit(1);
+    });
+    Profiler::end_profiling();
+    Profiler::end_profiling();
+
+    let profile = File::open(output_dir.join("panic.wtf")).map(Profile::open);
+    let _ = std::fs::remove_dir_all(&output_dir);
+    let mut profile = profile.unwrap().unwrap();
+    assert_eq!(profile.frame_count(), 4);
+    for frame in profile.frames_from(0).unwrap() {
+        assert_eq!(&*frame.unwrap().subtasks[0].name, "update");
+    }
+}
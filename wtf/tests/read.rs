@@ -0,0 +1,206 @@
+mod common;
+
+use std::io::Cursor;
+use std::time::Duration;
+use wtf::{Error, Profile, ProfileReader, ProfileWriter, TaskData};
+
+/// Enough frames with different names to fill several chunks
+fn many_frames() -> Vec<TaskData> {
+    (0..300)
+        .map(|i| {
+            let mut frame = common::task(&format!("Frame #{}", i + 1), i * 100, 90, 0);
+            frame.subtasks = vec![
+                common::task(&format!("load_chunk {}", i % 7), i * 100 + 5, 40, 1),
+                common::task("update", i * 100 + 50, 30, 0),
+            ]
+            .into();
+            frame
+        })
+        .collect()
+}
+
+#[test]
+fn read_v2() {
+    let frames = common::sample_profile();
+    let profile = common::write_profile(&frames, 1);
+    common::assert_same_frames(&wtf::read_profile_data(&profile[..]).unwrap(), &frames);
+
+    // Frames are also read one at a time, and can be skipped
+    let mut reader = ProfileReader::new(&profile[..]).unwrap();
+    assert_eq!(reader.skip_frames(2).unwrap(), 2);
+    let rest = reader.map(Result::unwrap).collect::<Vec<_>>();
+    common::assert_same_frames(&rest, &frames[2..]);
+}
+
+#[test]
+fn read_v1() {
+    let frames = common::without_v2_data(&common::sample_profile());
+    let metadata = [("program".to_string(), "game".to_string())];
+    let profile = common::write_v1(&frames, &metadata);
+    common::assert_same_frames(&wtf::read_profile_data(&profile[..]).unwrap(), &frames);
+    assert_eq!(
+        ProfileReader::new(&profile[..]).unwrap().metadata(),
+        &metadata
+    );
+}
+
+#[test]
+fn read_v0() {
+    let frames = common::sample_profile();
+    let profile = common::write_v0(&frames);
+    let read = wtf::read_profile_data(&profile[..]).unwrap();
+
+    // Version 0 only stored names, durations, and subtasks
+    fn check(read: &TaskData, written: &TaskData) {
+        assert_eq!(read.name, written.name);
+        assert_eq!(read.start, None);
+        assert_eq!(read.duration, written.duration);
+        assert_eq!(read.thread, 0);
+        assert!(read.args.is_empty());
+        assert_eq!(read.subtasks.len(), written.subtasks.len());
+        for (read, written) in read.subtasks.iter().zip(written.subtasks.iter()) {
+            check(read, written);
+        }
+    }
+    assert_eq!(read.len(), frames.len());
+    for (read, written) in read.iter().zip(&frames) {
+        check(read, written);
+    }
+}
+
+#[test]
+fn not_a_profile() {
+    assert!(matches!(
+        wtf::read_profile_data(&b"not a profile"[..]),
+        Err(Error::NotAProfile)
+    ));
+    let mut future = b"WTFPROF\0".to_vec();
+    future.extend(99u32.to_le_bytes());
+    assert!(matches!(
+        wtf::read_profile_data(&future[..]),
+        Err(Error::UnsupportedVersion { version: 99 })
+    ));
+}
+
+/// A profile cut off anywhere after its header still has its complete frames read by the lenient reader, while the strict
+/// reader only succeeds if the profile was cut off between frames
+#[test]
+fn read_truncated() {
+    let frames = common::sample_profile();
+    let profile = common::write_profile(&frames, 1);
+
+    let mut lenient_frames = 0;
+    let mut strict_errors = 0;
+    for len in 0..=profile.len() {
+        let lenient = match wtf::read_profile_data_lenient(&profile[..len]) {
+            Ok(lenient) => lenient,
+            // Cut off before the end of the header
+            Err(Error::CorruptHeader | Error::NotAProfile) if lenient_frames == 0 => continue,
+            Err(err) => panic!("Lenient read of {} bytes failed: {}", len, err),
+        };
+        assert!(lenient.len() >= lenient_frames);
+        lenient_frames = lenient.len();
+        common::assert_same_frames(&lenient, &frames[..lenient.len()]);
+
+        match wtf::read_profile_data(&profile[..len]) {
+            Ok(strict) => common::assert_same_frames(&strict, &lenient),
+            Err(Error::TruncatedFrame { frame, .. }) => {
+                assert_eq!(frame, lenient.len());
+                strict_errors += 1;
+            }
+            Err(err) => panic!("Strict read of {} bytes failed: {}", len, err),
+        }
+    }
+    assert_eq!(lenient_frames, frames.len());
+    assert!(strict_errors > 0);
+}
+
+#[test]
+fn random_access() {
+    let frames = many_frames();
+    let profile = common::write_profile(&frames, 40);
+    let mut profile = Profile::open(Cursor::new(profile)).unwrap();
+    assert_eq!(profile.frame_count(), frames.len());
+
+    // Names are looked up in the string table from the index, even if they were first used in an earlier frame
+    for n in [0, 1, 39, 40, 41, 150, 299] {
+        common::assert_same_task(&profile.frame(n).unwrap().unwrap(), &frames[n]);
+    }
+    assert!(profile.frame(300).unwrap().is_none());
+
+    let rest = profile
+        .frames_from(250)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
+    common::assert_same_frames(&rest, &frames[250..]);
+    assert_eq!(profile.frames_from(300).unwrap().count(), 0);
+}
+
+/// Profiles without a complete index, such as those from a program that crashed, have it rebuilt by scanning the frames
+#[test]
+fn rebuild_index() {
+    let frames = many_frames();
+    let write = |finish: bool| {
+        let mut profile = Vec::new();
+        let mut writer = ProfileWriter::new(&mut profile).unwrap();
+        for (i, frame) in frames.iter().enumerate() {
+            if i == 100 {
+                writer.set_metadata("level", "castle").unwrap();
+            }
+            writer.write_frame(frame).unwrap();
+            if i % 40 == 39 {
+                writer.flush().unwrap();
+            }
+        }
+        if finish {
+            writer.finish().unwrap();
+        } else {
+            // Like a program that exits without ending profiling
+            drop(writer);
+        }
+        profile
+    };
+    let profile = write(true);
+    let unfinished = write(false);
+
+    // Without the trailer, the index can't be found
+    let without_trailer = profile[..profile.len() - 28].to_vec();
+    // Cut off partway through a frame, the frames before it are still readable
+    let cut_off = unfinished[..unfinished.len() / 2].to_vec();
+    let complete_frames = wtf::read_profile_data_lenient(&cut_off[..]).unwrap().len();
+    assert!(complete_frames > 100 && complete_frames < frames.len());
+
+    for (profile, frame_count) in [
+        (profile, frames.len()),
+        (without_trailer, frames.len()),
+        (unfinished, frames.len()),
+        (cut_off, complete_frames),
+    ] {
+        let mut profile = Profile::open(Cursor::new(profile)).unwrap();
+        assert_eq!(profile.frame_count(), frame_count);
+        for n in [0, 100, frame_count - 1] {
+            common::assert_same_task(&profile.frame(n).unwrap().unwrap(), &frames[n]);
+        }
+        assert!(profile
+            .metadata()
+            .contains(&("level".to_string(), "castle".to_string())));
+    }
+}
+
+/// Version 0 and 1 profiles have no index, so it's always rebuilt
+#[test]
+fn random_access_old_versions() {
+    let frames = many_frames();
+    let v1 = common::write_v1(&frames, &[]);
+    let mut profile = Profile::open(Cursor::new(v1)).unwrap();
+    assert_eq!(profile.frame_count(), frames.len());
+    common::assert_same_task(&profile.frame(123).unwrap().unwrap(), &frames[123]);
+
+    let v0 = common::write_v0(&frames);
+    let mut profile = Profile::open(Cursor::new(v0)).unwrap();
+    assert_eq!(profile.frame_count(), frames.len());
+    let frame = profile.frame(123).unwrap().unwrap();
+    assert_eq!(frame.name, frames[123].name);
+    assert_eq!(frame.duration, Duration::from_micros(90));
+}
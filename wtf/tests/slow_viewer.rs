@@ -0,0 +1,95 @@
+use std::io::BufReader;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use wtf::{Connection, ProfileReader, Profiler, ProfilerConfig, ServeAddress};
+
+/// A viewer that stops reading doesn't hold up frames being sent to other viewers
+#[test]
+fn stuck_viewer() {
+    let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .unwrap()
+        .port();
+    let address = ServeAddress::Tcp(SocketAddr::from((Ipv4Addr::LOCALHOST, port)));
+
+    let output_dir =
+        std::env::temp_dir().join(format!("wtf-slow-viewer-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("slow_viewer.wtf")
+            .capture_on_start(false)
+            .serve(address.clone()),
+    )
+    .unwrap();
+
+    // Connects first, but never reads anything
+    let stuck = Connection::connect(&address).unwrap();
+    let received = Arc::new(AtomicUsize::new(0));
+    let viewer = thread::spawn({
+        let received = Arc::clone(&received);
+        move || {
+            let connection = Connection::connect(&address).unwrap();
+            for frame in ProfileReader::new(BufReader::new(connection)).unwrap() {
+                frame.unwrap();
+                received.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    });
+    Profiler::start_capture().unwrap();
+
+    // Large frames that barely compress, to fill the stuck viewer's socket buffers quickly
+    let mut random = 0x2545_f491_4f6c_dd1d_u64;
+    let mut record_frame = || {
+        let payload = (0..64 * 1024)
+            .map(|_| {
+                random ^= random << 13;
+                random ^= random >> 7;
+                random ^= random << 17;
+                (b'!' + (random % 94) as u8) as char
+            })
+            .collect::<String>();
+        let _frame = Profiler::new_frame();
+        let _task = Profiler::profile_task("update").arg("payload", payload);
+    };
+    let wait_for = |count: usize| {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while received.load(Ordering::SeqCst) < count {
+            assert!(Instant::now() < deadline, "Viewer didn't receive the frame");
+            thread::sleep(Duration::from_millis(1));
+        }
+    };
+
+    // Wait for the viewer to start receiving frames, which may not be from the first frame
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while received.load(Ordering::SeqCst) == 0 {
+        assert!(
+            Instant::now() < deadline,
+            "Viewer didn't receive any frames"
+        );
+        record_frame();
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    // Each frame reaches the other viewer promptly, even once the stuck viewer's buffers are full
+    let mut slowest = Duration::default();
+    for _ in 0..300 {
+        let count = received.load(Ordering::SeqCst) + 1;
+        let start = Instant::now();
+        record_frame();
+        wait_for(count);
+        slowest = slowest.max(start.elapsed());
+    }
+    Profiler::end_profiling();
+    viewer.join().unwrap();
+    drop(stuck);
+    let _ = std::fs::remove_dir_all(&output_dir);
+    assert!(
+        slowest < Duration::from_millis(500),
+        "A frame took {:?} to reach the viewer",
+        slowest
+    );
+}
@@ -0,0 +1,91 @@
+use std::io::BufReader;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use wtf::{ArgValue, Connection, ProfileReader, Profiler, ProfilerConfig, ServeAddress};
+
+/// Frames served over a localhost socket arrive as they finish, match the saved profile, and end with the capture, and the
+/// socket is closed when profiling ends
+#[test]
+fn loopback() {
+    // Find a free port, which is very likely still free when the profiler listens on it
+    let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .unwrap()
+        .port();
+    let address = ServeAddress::Tcp(SocketAddr::from((Ipv4Addr::LOCALHOST, port)));
+
+    let output_dir = std::env::temp_dir().join(format!("wtf-stream-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("loopback.wtf")
+            .capture_on_start(false)
+            .serve(address.clone()),
+    )
+    .unwrap();
+    Profiler::set_metadata("level", "loopback");
+
+    // The viewer connects before the capture starts, and waits for it
+    let received = Arc::new(AtomicUsize::new(0));
+    let viewer = thread::spawn({
+        let received = Arc::clone(&received);
+        let address = address.clone();
+        move || {
+            let connection = Connection::connect(&address).unwrap();
+            let reader = ProfileReader::new(BufReader::new(connection)).unwrap();
+            let metadata = reader.metadata().to_vec();
+            let frames = reader
+                .map(|frame| {
+                    received.fetch_add(1, Ordering::SeqCst);
+                    frame.unwrap()
+                })
+                .collect::<Vec<_>>();
+            (metadata, frames)
+        }
+    });
+    Profiler::start_capture().unwrap();
+
+    // Keep recording frames until the viewer has received a few, which shows they're sent before the capture ends
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut frame = 0;
+    while received.load(Ordering::SeqCst) < 3 {
+        assert!(
+            Instant::now() < deadline,
+            "Viewer didn't receive any frames"
+        );
+        frame += 1;
+        let _frame = Profiler::new_frame();
+        let _task = Profiler::profile_task("update").arg("frame", frame);
+        thread::sleep(Duration::from_millis(1));
+    }
+    Profiler::end_profiling();
+    // Viewers are no longer accepted once profiling has ended
+    assert!(Connection::connect(&address).is_err());
+
+    let (metadata, frames) = viewer.join().unwrap();
+    assert!(metadata.contains(&("level".to_string(), "loopback".to_string())));
+    assert!(frames.len() >= 3);
+
+    let saved =
+        wtf::read_profile_data(std::fs::File::open(output_dir.join("loopback.wtf")).unwrap())
+            .unwrap();
+    let _ = std::fs::remove_dir_all(&output_dir);
+    // The viewer may have joined after the first frames, but receives every frame after that
+    let skipped = saved.len() - frames.len();
+    for (streamed, saved) in frames.iter().zip(saved[skipped..].iter()) {
+        assert_eq!(streamed.name, saved.name);
+        assert_eq!(streamed.start, saved.start);
+        assert_eq!(streamed.duration, saved.duration);
+        assert_eq!(streamed.subtasks.len(), 1);
+        assert_eq!(streamed.subtasks[0].name, saved.subtasks[0].name);
+        assert_eq!(streamed.subtasks[0].args, saved.subtasks[0].args);
+    }
+    let last = &frames.last().unwrap().subtasks[0];
+    assert_eq!(
+        &*last.args,
+        &[("frame".into(), ArgValue::Int(frame as i64))]
+    );
+}
@@ -0,0 +1,54 @@
+#![cfg(unix)]
+
+use std::io::BufReader;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use wtf::{Connection, ProfileReader, Profiler, ProfilerConfig, ServeAddress};
+
+/// Frames are served over a Unix socket, which is removed when profiling ends
+#[test]
+fn unix_socket() {
+    let output_dir =
+        std::env::temp_dir().join(format!("wtf-stream-unix-test-{}", std::process::id()));
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let path = output_dir.join("wtf.sock");
+    let address = ServeAddress::Unix(path.clone());
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("unix.wtf")
+            .capture_on_start(false)
+            .serve(address.clone()),
+    )
+    .unwrap();
+
+    let received = Arc::new(AtomicUsize::new(0));
+    let connection = Connection::connect(&address).unwrap();
+    let viewer = thread::spawn({
+        let received = Arc::clone(&received);
+        move || {
+            for frame in ProfileReader::new(BufReader::new(connection)).unwrap() {
+                frame.unwrap();
+                received.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    });
+    Profiler::start_capture().unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while received.load(Ordering::SeqCst) == 0 {
+        assert!(
+            Instant::now() < deadline,
+            "Viewer didn't receive any frames"
+        );
+        let _frame = Profiler::new_frame();
+        thread::sleep(Duration::from_millis(1));
+    }
+    Profiler::end_profiling();
+    viewer.join().unwrap();
+    let socket_exists = path.exists();
+    let _ = std::fs::remove_dir_all(&output_dir);
+    assert!(!socket_exists);
+}
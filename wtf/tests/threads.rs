@@ -0,0 +1,61 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use wtf::{ArgValue, Profiler, ProfilerConfig};
+
+/// A worker's tasks belong to the frame they ran in, even though the worker records nothing after the frame ends,
+/// and its tasks after the last frame are still saved
+#[test]
+fn worker_tasks_belong_to_their_frame() {
+    let output_dir = std::env::temp_dir().join(format!("wtf-threads-test-{}", std::process::id()));
+    Profiler::configure(
+        ProfilerConfig::new()
+            .output_dir(&output_dir)
+            .file_name("threads.wtf"),
+    )
+    .unwrap();
+
+    // The worker waits for a job without recording anything, so it only records one task per job
+    let (jobs, job_receiver) = mpsc::channel::<i64>();
+    let (done_sender, done) = mpsc::channel();
+    let worker = thread::spawn(move || {
+        for job in job_receiver {
+            {
+                let _task = Profiler::profile_task("job").arg("job", job);
+                thread::sleep(Duration::from_millis(2));
+            }
+            done_sender.send(()).unwrap();
+        }
+    });
+
+    for job in 1..=5 {
+        let _frame = Profiler::new_frame();
+        jobs.send(job).unwrap();
+        done.recv().unwrap();
+    }
+    // This job finishes after the last frame, while the worker is still running
+    jobs.send(6).unwrap();
+    done.recv().unwrap();
+    Profiler::end_profiling();
+    drop(jobs);
+    worker.join().unwrap();
+
+    let frames =
+        wtf::read_profile_data(std::fs::File::open(output_dir.join("threads.wtf")).unwrap())
+            .unwrap();
+    let _ = std::fs::remove_dir_all(&output_dir);
+    assert_eq!(frames.len(), 6);
+    for (job, frame) in (1..=6).zip(frames.iter()) {
+        assert_eq!(
+            frame.subtasks.len(),
+            1,
+            "{} has the wrong tasks",
+            frame.name
+        );
+        let task = &frame.subtasks[0];
+        assert_eq!(&*task.args, &[("job".into(), ArgValue::Int(job))]);
+        let (frame_start, task_start) = (frame.start.unwrap(), task.start.unwrap());
+        assert!(frame_start <= task_start);
+        assert!(task_start + task.duration <= frame_start + frame.duration);
+    }
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use std::io::Cursor;
+use wtf::{Profile, ProfileWriter};
+
+/// Everything written with a [`ProfileWriter`] is read back the same
+#[test]
+fn round_trip() {
+    let frames = common::sample_profile();
+    let mut writer = ProfileWriter::new(Vec::new()).unwrap();
+    writer.set_metadata("level", "castle").unwrap();
+    for frame in &frames {
+        writer.write_frame(frame).unwrap();
+    }
+    let profile = writer.finish().unwrap();
+
+    let read = wtf::read_profile_data(profile.as_slice()).unwrap();
+    common::assert_same_frames(&read, &frames);
+
+    let profile = Profile::open(Cursor::new(profile)).unwrap();
+    assert!(profile
+        .metadata()
+        .contains(&("level".to_string(), "castle".to_string())));
+}
+
+/// Frames written before [`ProfileWriter::finish`] are still readable if the writer is dropped, only without an index
+#[test]
+fn dropped_writer() {
+    let frames = common::sample_profile();
+    let mut profile = Vec::new();
+    {
+        let mut writer = ProfileWriter::new(&mut profile).unwrap();
+        for frame in &frames {
+            writer.write_frame(frame).unwrap();
+        }
+    }
+
+    let read = wtf::read_profile_data(profile.as_slice()).unwrap();
+    common::assert_same_frames(&read, &frames);
+}